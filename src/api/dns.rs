@@ -1,30 +1,44 @@
-use super::{Json, Result, ok};
+use super::{Json, Query, Result, ok};
 use crate::{
     api::{
-        dns::State::{Disabled, Failure, Pending, Success},
+        dns::State::{Disabled, Failure, Paused, Pending, Success},
         error::ApiError,
     },
-    model::{AddrConfig, DnsConfig, DnsState, Domain},
-    service::AppCtx,
+    model::{
+        AddrConfig, DnsConfig, DnsState, Domain, ErrorKind,
+        Family::{self, Ipv4, Ipv6},
+        HistoryEntry, Method,
+    },
+    service::{
+        AppCtx,
+        task::{self, Planned},
+    },
 };
 use axum::{
     Extension, Router,
     extract::Path,
     routing::{delete, get, post, put},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 
 pub fn router() -> Router {
     Router::new()
+        .route("/dns", get(list))
         .route("/dns/{name}", get(get_by_name))
         .route("/dns", post(save))
         .route("/dns/{name}", put(update))
         .route("/dns/{name}", delete(remove))
         .route("/dns/state/list", get(state))
+        .route("/dns/source/list", get(sources))
         .route("/dns/run/{name}", put(run))
+        .route("/dns/dry-run/plan", post(dry_run))
+        .route("/dns/{name}/pause", put(pause))
+        .route("/dns/{name}/resume", put(resume))
+        .route("/dns/{name}/history", get(history))
 }
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct DnsStateView {
     name: String,
     #[serde(flatten)]
@@ -32,27 +46,48 @@ struct DnsStateView {
     kind: Option<&'static str>,
     ipv4: State,
     ipv6: State,
+    next_run: Option<u64>,
+    succeeded: u64,
+    failed: u64,
 }
 
 #[derive(Serialize)]
 #[serde(tag = "state")]
 #[serde(rename_all = "camelCase")]
 enum State {
-    Success { timestamp: u64, addr: IpAddr },
-    Failure { timestamp: u64, message: String },
+    Success {
+        timestamp: u64,
+        addr: IpAddr,
+    },
+    Failure {
+        timestamp: u64,
+        message: String,
+        error: Option<ErrorKind>,
+    },
     Pending,
+    Paused,
     Disabled,
 }
 impl From<DnsState> for State {
     fn from(value: DnsState) -> Self {
         match value {
             DnsState::Succeed { timestamp, addr } => Success { timestamp, addr },
-            DnsState::Failed { timestamp, message } => Failure { timestamp, message },
+            DnsState::Failed {
+                timestamp,
+                message,
+                error,
+            } => Failure {
+                timestamp,
+                message,
+                error,
+            },
         }
     }
 }
 
-async fn state(Extension(AppCtx { store, .. }): Extension<AppCtx>) -> Result<Vec<DnsStateView>> {
+async fn state(
+    Extension(AppCtx { store, manager, .. }): Extension<AppCtx>,
+) -> Result<Vec<DnsStateView>> {
     let configs = store.list_dns_configs().await;
     let mut views = Vec::with_capacity(configs.len());
     for config in configs {
@@ -62,12 +97,18 @@ async fn state(Extension(AppCtx { store, .. }): Extension<AppCtx>) -> Result<Vec
             ipv4,
             ipv6,
             provider,
+            paused,
             ..
         } = config;
         let ty = store.get_dns_provider(&provider).await;
-        let ipv4 = construct_state(ipv4);
-        let ipv6 = construct_state(ipv6);
+        let runtime = store.get_dns_state(&name).await.unwrap_or_default();
+        let ipv4 = construct_state(ipv4, runtime.ipv4, paused);
+        let ipv6 = construct_state(ipv6, runtime.ipv6, paused);
+        let next_run = manager.next_run(&name);
         views.push(DnsStateView {
+            next_run,
+            succeeded: runtime.succeeded,
+            failed: runtime.failed,
             name,
             domain,
             kind: ty.map(|p| p.config.ty()),
@@ -77,20 +118,79 @@ async fn state(Extension(AppCtx { store, .. }): Extension<AppCtx>) -> Result<Vec
     }
     ok(views)
 }
+/// an address source and the configs sharing it
+#[derive(Serialize)]
+struct SourceView {
+    #[serde(flatten)]
+    method: Method,
+    family: Family,
+    configs: Vec<String>,
+    /// addresses of the latest probe
+    addrs: Vec<IpAddr>,
+    timestamp: Option<u64>,
+}
+
+async fn sources(
+    Extension(AppCtx { store, detect, .. }): Extension<AppCtx>,
+) -> Result<Vec<SourceView>> {
+    let mut views: Vec<SourceView> = Vec::new();
+    for config in store.list_dns_configs().await {
+        let DnsConfig {
+            name,
+            ipv4,
+            ipv6,
+            paused,
+            ..
+        } = config;
+        if paused {
+            continue;
+        }
+        for (cfg, family) in [(ipv4, Ipv4), (ipv6, Ipv6)] {
+            let Some(cfg) = cfg.filter(|cfg| cfg.enabled) else {
+                continue;
+            };
+            match views
+                .iter_mut()
+                .find(|view| view.method == cfg.method && view.family == family)
+            {
+                Some(view) => view.configs.push(name.clone()),
+                None => {
+                    let probe = detect.last(&cfg.method, family);
+                    views.push(SourceView {
+                        method: cfg.method,
+                        family,
+                        configs: vec![name.clone()],
+                        timestamp: probe.as_ref().map(|probe| probe.timestamp),
+                        addrs: probe.map(|probe| probe.addrs).unwrap_or_default(),
+                    });
+                }
+            }
+        }
+    }
+    ok(views)
+}
+
 #[inline]
-fn construct_state(config: Option<AddrConfig>) -> State {
+fn construct_state(config: Option<AddrConfig>, state: Option<DnsState>, paused: bool) -> State {
     let Some(config) = config else {
         return Disabled;
     };
     if !config.enabled {
         return Disabled;
     };
-    match config.state {
+    if paused {
+        return Paused;
+    }
+    match state {
         None => Pending,
         Some(state) => state.into(),
     }
 }
 
+async fn list(Extension(ctx): Extension<AppCtx>) -> Result<Vec<DnsConfig>> {
+    ok(ctx.store.list_dns_configs().await)
+}
+
 async fn get_by_name(
     Extension(ctx): Extension<AppCtx>,
     Path(name): Path<String>,
@@ -105,11 +205,20 @@ async fn save(Extension(ctx): Extension<AppCtx>, Json(config): Json<DnsConfig>)
     save0(ctx, config).await
 }
 
-async fn update(Extension(ctx): Extension<AppCtx>, Json(config): Json<DnsConfig>) -> Result<()> {
+async fn update(
+    Extension(ctx): Extension<AppCtx>,
+    Json(mut config): Json<DnsConfig>,
+) -> Result<()> {
+    // only changed through pause/resume
+    if let Some(stored) = ctx.store.get_dns_config(&config.name).await {
+        config.paused = stored.paused;
+    }
     save0(ctx, config).await
 }
 
 async fn remove(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) -> Result<()> {
+    // the task is kept when the config can't be deleted
+    ctx.store.writable()?;
     ctx.manager.delete_task(&name).await;
     ctx.store.delete_dns_config(&name).await?;
     ok(())
@@ -119,22 +228,140 @@ async fn run(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) -> Res
     let Some(config) = ctx.store.get_dns_config(&name).await else {
         return ApiError::BadRequest(format!("dns config not found: {name}",)).into();
     };
-    ctx.manager.delete_task(&name).await;
-    ctx.manager.create_task(config, ctx.store).await?;
+    if !ctx.manager.run_task(&name)? {
+        ctx.manager.reload_task(config, ctx.store).await;
+    }
     ok(())
 }
 
-async fn save0(AppCtx { store, manager, .. }: AppCtx, config: DnsConfig) -> Result<()> {
-    if store.get_dns_provider(&config.provider).await.is_none() {
-        return ApiError::BadRequest(format!("provider not found: {}", &config.provider)).into();
+/// what running the config of the form would change, nothing is written
+async fn dry_run(
+    Extension(ctx): Extension<AppCtx>,
+    Json(config): Json<DnsConfig>,
+) -> Result<Vec<Planned>> {
+    let interfaces = local_ip_address::list_afinet_netifas()?;
+    ctx.store.check_dns_config(&config, &interfaces).await?;
+    ok(task::dry_run(&config, &ctx.store, &ctx.detect).await?)
+}
+
+#[derive(Deserialize)]
+struct PageParam {
+    /// starts from 1
+    #[serde(default = "PageParam::page")]
+    page: usize,
+    #[serde(default = "PageParam::size")]
+    size: usize,
+}
+impl PageParam {
+    fn page() -> usize {
+        1
     }
-    if let Some(webhook) = &config.webhook
-        && store.get_webhook(webhook).await.is_none()
-    {
-        return ApiError::BadRequest(format!("webhook not found: {webhook}",)).into();
+    fn size() -> usize {
+        20
     }
-    manager.delete_task(&config.name).await;
+}
+
+#[derive(Serialize)]
+struct Page<T> {
+    total: usize,
+    items: Vec<T>,
+}
+
+/// newest first
+async fn history(
+    Extension(ctx): Extension<AppCtx>,
+    Path(name): Path<String>,
+    Query(PageParam { page, size }): Query<PageParam>,
+) -> Result<Page<HistoryEntry>> {
+    if ctx.store.get_dns_config(&name).await.is_none() {
+        return ApiError::BadRequest(format!("dns config not found: {name}",)).into();
+    }
+    if page == 0 || !(1..=100).contains(&size) {
+        return ApiError::BadRequest("page starts from 1, size must be 1-100".into()).into();
+    }
+    let offset = (page - 1).saturating_mul(size);
+    let (total, items) = ctx.store.get_dns_history(&name, offset, size).await;
+    ok(Page { total, items })
+}
+
+async fn pause(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) -> Result<()> {
+    set_paused(ctx, name, true).await
+}
+
+async fn resume(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) -> Result<()> {
+    set_paused(ctx, name, false).await
+}
+
+async fn set_paused(
+    AppCtx { store, manager, .. }: AppCtx,
+    name: String,
+    paused: bool,
+) -> Result<()> {
+    let Some(mut config) = store.get_dns_config(&name).await else {
+        return ApiError::BadRequest(format!("dns config not found: {name}",)).into();
+    };
+    config.paused = paused;
+    store.save_dns_config(&config).await?;
+    manager.reload_task(config, store).await;
+    ok(())
+}
+
+async fn save0(AppCtx { store, manager, .. }: AppCtx, config: DnsConfig) -> Result<()> {
+    let interfaces = local_ip_address::list_afinet_netifas()?;
+    store.check_dns_config(&config, &interfaces).await?;
     store.save_dns_config(&config).await?;
-    manager.create_task(config, store).await?;
+    manager.reload_task(config, store).await;
     ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::{
+        StoreService,
+        testing::{sample_config, temp_store},
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        // the detection fails at once, the provider is never called
+        let mut config = sample_config("cf");
+        config["ipv4"] =
+            serde_json::json!({ "enabled": true, "method": "cmd", "command": "exit 1" });
+        let content = serde_json::json!({
+            "listen": "0.0.0.0:6789",
+            "ddns": [config],
+            "provider": [{ "name": "cf", "kind": "cloudflare", "apiKey": "key" }],
+        });
+        let (path, store) = temp_store("pause", Some(&content)).await;
+        let ctx = AppCtx::with_store(Arc::new(store)).await;
+        let paused = |ctx: AppCtx| async move {
+            let views = state(Extension(ctx)).await.unwrap().data.unwrap();
+            matches!(views[0].ipv4, Paused)
+        };
+
+        pause(Extension(ctx.clone()), Path("home".into()))
+            .await
+            .unwrap();
+        assert!(paused(ctx.clone()).await);
+
+        // editing the config keeps it paused, on disk too
+        let edited: DnsConfig = serde_json::from_value(config).unwrap();
+        update(Extension(ctx.clone()), Json(edited)).await.unwrap();
+        assert!(paused(ctx.clone()).await);
+        let restarted = StoreService::new(path.clone(), Default::default());
+        let restarted = restarted.await.unwrap();
+        assert!(restarted.get_dns_config("home").await.unwrap().paused);
+
+        resume(Extension(ctx.clone()), Path("home".into()))
+            .await
+            .unwrap();
+        assert!(!paused(ctx.clone()).await);
+        assert!(!ctx.store.get_dns_config("home").await.unwrap().paused);
+        ctx.manager.delete_task("home").await;
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::api::ApiResult;
+use crate::{api::ApiResult, model::validate::ValidationError, provider::ProviderError};
 use axum::{
     extract::rejection::{JsonRejection, QueryRejection},
     http::Uri,
@@ -24,6 +24,9 @@ pub enum ApiError {
     #[error("bad request: {0}")]
     BadRequest(String),
 
+    #[error("invalid config: {0}")]
+    Invalid(#[from] ValidationError),
+
     #[error("path not found: {0}")]
     NotFound(Uri),
 
@@ -41,6 +44,9 @@ pub enum ApiError {
 
     #[error("find local addr error: {0}")]
     NicError(#[from] local_ip_address::Error),
+
+    #[error("provider error: {0}")]
+    Provider(#[from] ProviderError),
 }
 impl<T> Into<super::Result<T>> for ApiError {
     fn into(self) -> super::Result<T> {
@@ -69,6 +75,15 @@ impl IntoResponse for ApiError {
                 message: Some(message.into()),
                 data: None,
             },
+            // the form highlights the fields
+            ApiError::Invalid(e) => {
+                return ApiResult {
+                    code: 1100,
+                    message: Some(e.to_string().into()),
+                    data: Some(e.0),
+                }
+                .into_response();
+            }
             ApiError::InvalidCredential(message) => ApiResult::<()> {
                 code: 1200,
                 message: Some(message.into()),
@@ -106,6 +121,15 @@ impl IntoResponse for ApiError {
                 message: Some(e.to_string().into()),
                 data: None,
             },
+            // the kind tells a picker whether the credentials are wrong
+            ApiError::Provider(e) => {
+                return ApiResult {
+                    code: 1900,
+                    message: Some(e.to_string().into()),
+                    data: Some(e.kind()),
+                }
+                .into_response();
+            }
             ApiError::QueryRejection(e) => ApiResult::<()> {
                 code: 1100,
                 message: Some(e.to_string().into()),
@@ -13,6 +13,7 @@ mod error;
 mod middleware;
 mod provider;
 mod sys;
+mod task;
 mod user;
 mod webhook;
 
@@ -25,6 +26,7 @@ pub fn router(app: AppCtx) -> Router {
         .merge(dns::router())
         .merge(provider::router())
         .merge(webhook::router())
+        .merge(task::router())
         .fallback(not_found)
         .layer(from_fn(middleware::auth_layer))
         .layer(Extension(app));
@@ -70,3 +72,48 @@ where
         axum::Json(self).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn actions_do_not_shadow_names() {
+        let router = Router::new()
+            .merge(dns::router())
+            .merge(provider::router())
+            .merge(webhook::router());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        let client = reqwest::Client::new();
+        // configs and providers named like the actions, and the actions themselves
+        let requests = [
+            (Method::GET, "/dns"),
+            (Method::GET, "/dns/list"),
+            (Method::GET, "/dns/dry-run"),
+            (Method::PUT, "/dns/dry-run"),
+            (Method::DELETE, "/dns/dry-run"),
+            (Method::PUT, "/dns/run/dry-run"),
+            (Method::PUT, "/dns/plan/pause"),
+            (Method::POST, "/dns/dry-run/plan"),
+            (Method::PUT, "/provider/test"),
+            (Method::DELETE, "/provider/test"),
+            (Method::PUT, "/provider/run"),
+            (Method::DELETE, "/provider/run"),
+            (Method::GET, "/provider/run/zones"),
+            (Method::POST, "/provider/run/test"),
+        ];
+        for (method, path) in requests {
+            let response = client
+                .request(method.clone(), format!("http://{addr}{path}"))
+                .send()
+                .await
+                .unwrap();
+            // the handler is reached, it fails without the app
+            assert_eq!(response.status(), 500, "{method} {path}");
+        }
+    }
+}
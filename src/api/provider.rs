@@ -1,6 +1,7 @@
 use crate::{
     api::{Json, Result, error::ApiError, ok},
-    model::Provider,
+    model::{Provider, validate::Validate},
+    provider::{self, CheckResult, Record, Zone},
     service::AppCtx,
 };
 use axum::{
@@ -15,20 +16,34 @@ pub fn router() -> Router<()> {
         .route("/provider", post(save))
         .route("/provider/{name}", put(update))
         .route("/provider/{name}", delete(remove))
+        .route("/provider/run/test", post(test))
+        .route("/provider/{name}/zones", get(zones))
+        .route("/provider/{name}/zones/{zone}/records", get(records))
 }
 
 async fn list(Extension(ctx): Extension<AppCtx>) -> Result<Vec<Provider>> {
-    ok(ctx.store.list_dns_providers().await)
+    let mut providers = ctx.store.list_dns_providers().await;
+    providers.iter_mut().for_each(Provider::redact);
+    ok(providers)
 }
 
 async fn save(Extension(ctx): Extension<AppCtx>, Json(provider): Json<Provider>) -> Result<()> {
+    provider.validate()?;
     if ctx.store.get_dns_provider(&provider.name).await.is_some() {
         return ApiError::BadRequest(format!("duplicate name: {}", &provider.name)).into();
     }
     ctx.store.save_dns_provider(&provider).await?;
     ok(())
 }
-async fn update(Extension(ctx): Extension<AppCtx>, Json(provider): Json<Provider>) -> Result<()> {
+async fn update(
+    Extension(ctx): Extension<AppCtx>,
+    Json(mut provider): Json<Provider>,
+) -> Result<()> {
+    // the form sends back the redacted secrets it did not change
+    if let Some(stored) = ctx.store.get_dns_provider(&provider.name).await {
+        provider.restore(&stored);
+    }
+    provider.validate()?;
     ctx.store.save_dns_provider(&provider).await?;
     ok(())
 }
@@ -37,3 +52,35 @@ async fn remove(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) ->
     ctx.store.delete_dns_provider(&name).await?;
     ok(())
 }
+
+/// checks the credentials of the form, it may not be saved yet
+async fn test(
+    Extension(ctx): Extension<AppCtx>,
+    Json(mut provider): Json<Provider>,
+) -> Result<CheckResult> {
+    if let Some(stored) = ctx.store.get_dns_provider(&provider.name).await {
+        provider.restore(&stored);
+    }
+    provider.validate()?;
+    ok(provider::check(&provider).await)
+}
+
+async fn zones(Extension(ctx): Extension<AppCtx>, Path(name): Path<String>) -> Result<Vec<Zone>> {
+    let provider = find(&ctx, &name).await?;
+    ok(provider::list_zones(&provider).await?)
+}
+
+async fn records(
+    Extension(ctx): Extension<AppCtx>,
+    Path((name, zone)): Path<(String, String)>,
+) -> Result<Vec<Record>> {
+    let provider = find(&ctx, &name).await?;
+    ok(provider::list_records(&provider, &zone).await?)
+}
+
+async fn find(ctx: &AppCtx, name: &str) -> std::result::Result<Provider, ApiError> {
+    match ctx.store.get_dns_provider(name).await {
+        Some(provider) => Ok(provider),
+        None => Err(ApiError::BadRequest(format!("provider not found: {name}"))),
+    }
+}
@@ -27,6 +27,8 @@ pub fn router() -> Router {
 pub struct SysInfo {
     config_path: String,
     initialized: bool,
+    /// the config is declared by the deployment, changes are refused
+    read_only: bool,
     version: &'static str,
     commit_id: &'static str,
 }
@@ -37,6 +39,7 @@ async fn sys_info(Extension(ctx): Extension<AppCtx>) -> Result<SysInfo> {
     ok(SysInfo {
         config_path: path.to_string_lossy().to_string(),
         initialized: ctx.store.get_user().await.is_some(),
+        read_only: ctx.store.is_read_only(),
         version: build::PKG_VERSION,
         commit_id: build::SHORT_COMMIT,
     })
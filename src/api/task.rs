@@ -0,0 +1,23 @@
+use super::{Result, ok};
+use crate::service::{AppCtx, dns::TaskState};
+use axum::{Extension, Router, routing::get};
+use serde::Serialize;
+
+pub fn router() -> Router {
+    Router::new().route("/task/list", get(list))
+}
+
+#[derive(Serialize)]
+struct TaskView {
+    name: String,
+    #[serde(flatten)]
+    state: TaskState,
+}
+
+async fn list(Extension(ctx): Extension<AppCtx>) -> Result<Vec<TaskView>> {
+    let tasks = ctx.manager.list();
+    ok(tasks
+        .into_iter()
+        .map(|(name, state)| TaskView { name, state })
+        .collect())
+}
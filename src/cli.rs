@@ -1,34 +1,78 @@
+use crate::model::Family;
 use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
 
 #[derive(Parser)]
 #[command(name = "ddns-rs", about)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+    /// `run` is the default command
+    #[command(flatten)]
+    pub run: RunArgs,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Run DDNS
     Run(RunArgs),
+    /// Manage Config Files
+    #[command(subcommand)]
+    Config(ConfigCommands),
+    /// Update The Records Once And Exit, For Cron Or Systemd Timers
+    Once(OnceArgs),
+    /// Manage DNS Configs, Through The Running Instance If Any
+    #[command(subcommand)]
+    Dns(DnsCommands),
+    /// Manage Providers, Through The Running Instance If Any
+    #[command(subcommand)]
+    Provider(ProviderCommands),
+    /// Manage Webhooks, Through The Running Instance If Any
+    #[command(subcommand)]
+    Webhook(WebhookCommands),
+    /// Manage The Login User
+    #[command(subcommand)]
+    User(UserCommands),
+    /// Show The Addresses A Method Reports And The One Chosen
+    Detect(Box<DetectArgs>),
 }
-impl Default for Commands {
-    fn default() -> Self {
-        Self::Run(RunArgs::default())
-    }
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// Listen Address, Overrides `listen` In The Config
+    #[arg(long, env = "DDNS_RS_LISTEN")]
+    pub listen: Option<String>,
+    /// Log Level: off, error, warn, info, debug, trace
+    #[arg(long, env = "DDNS_RS_LOG_LEVEL", default_value = "info")]
+    pub log_level: LevelFilter,
+    /// Config Content In YAML Or JSON Instead Of A File, Implies --read-only
+    #[arg(long, env = "DDNS_RS_CONFIG_DATA", hide_env_values = true)]
+    pub config_data: Option<String>,
+    /// Refuse Changes From The Web UI And API, The Config File Is Never Written
+    #[arg(long, env = "DDNS_RS_READ_ONLY")]
+    pub read_only: bool,
 }
+
 #[derive(Args, Default)]
-pub struct RunArgs {
+pub struct Location {
     /// Config Location
-    #[arg(short)]
+    #[arg(short, env = "DDNS_RS_CONFIG")]
     config: Option<PathBuf>,
+    /// Directory Of The Config And State Files, The State File Is Kept Next To The Config By Default
+    #[arg(long, env = "DDNS_RS_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
 }
-impl RunArgs {
+impl Location {
     pub fn config(&self) -> PathBuf {
         if let Some(path) = &self.config {
             return path.to_path_buf();
         }
+        if let Some(dir) = &self.data_dir {
+            return dir.join("ddns-rs.conf");
+        }
         //current dir
         #[cfg(windows)]
         if let Ok(dir) = std::env::current_dir() {
@@ -42,3 +86,190 @@ impl RunArgs {
         panic!("Unable to detect configuration directory, please specify with -c")
     }
 }
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Convert A Config File, The Format Is Chosen By Extension (.json, .toml, .yaml)
+    Convert(ConvertArgs),
+    /// Create A Key File To Encrypt The Secrets In The Config
+    Keygen(Location),
+}
+#[derive(Args)]
+pub struct OnceArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// DNS Config To Update, All Unpaused Configs By Default
+    #[arg(long = "config", value_name = "NAME")]
+    pub names: Vec<String>,
+    /// Print The Results As JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Config Content In YAML Or JSON Instead Of A File
+    #[arg(long, env = "DDNS_RS_CONFIG_DATA", hide_env_values = true)]
+    pub config_data: Option<String>,
+    /// Log Level Of The Messages Printed To Stderr
+    #[arg(long, env = "DDNS_RS_LOG_LEVEL", default_value = "warn")]
+    pub log_level: LevelFilter,
+}
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Config To Read
+    pub input: PathBuf,
+    /// Config To Write
+    pub output: PathBuf,
+    /// Overwrite The Output If It Exists
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Subcommand)]
+pub enum DnsCommands {
+    /// List The DNS Configs
+    List(ListArgs),
+    /// Print A DNS Config In YAML
+    Show(ShowArgs),
+    /// Add A DNS Config From A YAML, JSON Or TOML File
+    Add(AddArgs),
+    /// Remove A DNS Config
+    Rm(NameArgs),
+    /// Update The Records Now
+    Run(RunDnsArgs),
+}
+#[derive(Subcommand)]
+pub enum ProviderCommands {
+    /// List The Providers, Secrets Are Hidden
+    List(ListArgs),
+    /// Add A Provider From A YAML, JSON Or TOML File
+    Add(AddArgs),
+    /// Remove A Provider
+    Rm(NameArgs),
+    /// Sign In With The Credentials And List The Zones They Can See
+    Test(ShowArgs),
+}
+#[derive(Subcommand)]
+pub enum WebhookCommands {
+    /// List The Webhooks
+    List(ListArgs),
+    /// Add A Webhook, The Request Is Read From A File
+    Add(WebhookAddArgs),
+    /// Remove A Webhook
+    Rm(NameArgs),
+    /// Send The Webhook With Sample Results
+    Test(NameArgs),
+}
+#[derive(Subcommand)]
+pub enum UserCommands {
+    /// Set The Password, Read From Stdin After The Current One If A Server Is Running
+    Passwd(PasswdArgs),
+}
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// Print As JSON
+    #[arg(long)]
+    pub json: bool,
+}
+#[derive(Args)]
+pub struct ShowArgs {
+    #[command(flatten)]
+    pub location: Location,
+    pub name: String,
+    /// Print As JSON
+    #[arg(long)]
+    pub json: bool,
+}
+#[derive(Args)]
+pub struct NameArgs {
+    #[command(flatten)]
+    pub location: Location,
+    pub name: String,
+}
+#[derive(Args)]
+pub struct RunDnsArgs {
+    #[command(flatten)]
+    pub location: Location,
+    pub name: String,
+    /// Only Show What Would Be Created Or Updated
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print The Dry Run As JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+}
+#[derive(Args)]
+pub struct AddArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// File To Read, Stdin If Omitted
+    pub file: Option<PathBuf>,
+    /// Replace An Existing One With The Same Name
+    #[arg(short, long)]
+    pub force: bool,
+}
+#[derive(Args)]
+pub struct WebhookAddArgs {
+    #[command(flatten)]
+    pub location: Location,
+    pub name: String,
+    /// Request In The Raw HTTP Syntax, Stdin If Omitted
+    pub file: Option<PathBuf>,
+    /// Replace An Existing One With The Same Name
+    #[arg(short, long)]
+    pub force: bool,
+}
+#[derive(Args)]
+pub struct PasswdArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// Username, The Current One By Default
+    #[arg(short, long)]
+    pub username: Option<String>,
+}
+#[derive(Args)]
+pub struct DetectArgs {
+    #[command(flatten)]
+    pub location: Location,
+    /// Use The Methods Of A DNS Config
+    #[arg(long = "config", value_name = "NAME", conflicts_with = "method")]
+    pub name: Option<String>,
+    /// Method: api, nic, cmd, dns, upnp, natpmp
+    #[arg(long, required_unless_present = "name")]
+    pub method: Option<String>,
+    /// Family: ipv4, ipv6
+    #[arg(long, default_value = "ipv4")]
+    pub family: Family,
+    /// Endpoint Of The api Method
+    #[arg(long)]
+    pub endpoint: Option<String>,
+    /// Interface Of The nic Method, The Flags Of Its IPv6 Addresses Are Only Shown On Linux
+    #[arg(long)]
+    pub interface: Option<String>,
+    /// Command Of The cmd Method
+    #[arg(long)]
+    pub command: Option<String>,
+    /// Server Of The dns Method
+    #[arg(long)]
+    pub server: Option<String>,
+    /// Query Of The dns Method
+    #[arg(long)]
+    pub query: Option<String>,
+    /// Record Of The dns Method: addr, txt
+    #[arg(long)]
+    pub record: Option<String>,
+    /// Class Of The dns Method: in, ch
+    #[arg(long)]
+    pub class: Option<String>,
+    /// Gateway Description Url Of The upnp Method
+    #[arg(long)]
+    pub upnp_location: Option<String>,
+    /// Gateway Of The natpmp Method
+    #[arg(long)]
+    pub gateway: Option<String>,
+    /// Fixed Suffix Combined With The Prefix, E.g. ::10
+    #[arg(long)]
+    pub suffix: Option<String>,
+    /// Print As JSON
+    #[arg(long)]
+    pub json: bool,
+}
@@ -0,0 +1,431 @@
+use crate::{
+    cli::{ConfigCommands, ConvertArgs, DetectArgs, Location, OnceArgs},
+    model::{
+        AddrConfig, DnsConfig, DnsState, Family, Method,
+        validate::{self, Validator},
+    },
+    service::{
+        detect::{self, DetectCache, Explained},
+        store::{self, StoreOptions, StoreService, format::Format},
+        task,
+    },
+};
+use anyhow::{Context, bail};
+use serde::Serialize;
+use time::UtcDateTime;
+
+pub use manage::{dns, provider, user, webhook};
+
+mod backend;
+mod manage;
+
+/// commands run without the log service, so errors are printed to stderr
+pub fn exit_on_error(result: anyhow::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("error: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+pub async fn config(command: ConfigCommands) -> anyhow::Result<()> {
+    match command {
+        ConfigCommands::Convert(args) => convert(args).await,
+        ConfigCommands::Keygen(args) => keygen(args).await,
+    }
+}
+
+async fn convert(
+    ConvertArgs {
+        input,
+        output,
+        force,
+    }: ConvertArgs,
+) -> anyhow::Result<()> {
+    if !force && tokio::fs::try_exists(&output).await? {
+        bail!(
+            "{} exists, use --force to overwrite it",
+            output.to_string_lossy()
+        );
+    }
+    store::convert(&input, &output).await?;
+    println!(
+        "converted {} ({}) to {} ({})",
+        input.to_string_lossy(),
+        Format::from_path(&input),
+        output.to_string_lossy(),
+        Format::from_path(&output)
+    );
+    Ok(())
+}
+
+async fn keygen(location: Location) -> anyhow::Result<()> {
+    let path = store::create_key(&location.config()).await?;
+    println!(
+        "created {}, secrets are encrypted when ddns-rs starts next",
+        path.to_string_lossy()
+    );
+    Ok(())
+}
+
+/// one family of a config updated by [`once`]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Outcome {
+    config: String,
+    /// none if the config could not run at all
+    family: Option<Family>,
+    #[serde(flatten)]
+    state: DnsState,
+    /// milliseconds
+    duration: u64,
+}
+
+pub async fn once(
+    OnceArgs {
+        location,
+        names,
+        json,
+        config_data,
+        log_level,
+    }: OnceArgs,
+) -> anyhow::Result<()> {
+    // stdout is kept for the results
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(log_level)
+        .init();
+    let options = StoreOptions {
+        data_dir: location.data_dir.clone(),
+        content: config_data,
+        // only the states are written
+        read_only: true,
+    };
+    let store = StoreService::new(location.config(), options).await?;
+    let configs = store.list_dns_configs().await;
+    let configs = if names.is_empty() {
+        configs.into_iter().filter(|c| !c.paused).collect()
+    } else {
+        let mut selected = Vec::with_capacity(names.len());
+        for name in &names {
+            match configs.iter().find(|c| &c.name == name) {
+                Some(config) => selected.push(config.clone()),
+                None => bail!("dns config not found: {name}"),
+            }
+        }
+        selected
+    };
+    let outcomes = run_configs(&store, configs).await;
+    store.persist_states().await?;
+    report(outcomes, json)
+}
+
+/// updates the configs one after another, as their tasks would
+async fn run_configs(store: &StoreService, configs: Vec<DnsConfig>) -> Vec<Outcome> {
+    let detect = DetectCache::default();
+    let mut outcomes = vec![];
+    for mut config in configs {
+        if store.get_dns_provider(&config.provider).await.is_none() {
+            outcomes.push(Outcome {
+                config: config.name.clone(),
+                family: None,
+                state: DnsState::Failed {
+                    timestamp: UtcDateTime::now().unix_timestamp() as _,
+                    message: format!("provider not found: {}", config.provider),
+                    error: None,
+                },
+                duration: 0,
+            });
+            continue;
+        }
+        let updated = task::run_once(&mut config, store, &detect, None).await;
+        task::notify(&config, store).await;
+        outcomes.extend(
+            updated
+                .into_iter()
+                .map(|(family, state, duration)| Outcome {
+                    config: config.name.clone(),
+                    family: Some(family),
+                    state,
+                    duration: duration.as_millis() as _,
+                }),
+        );
+    }
+    outcomes
+}
+
+/// prints the outcomes, an error if any update failed
+fn report(outcomes: Vec<Outcome>, json: bool) -> anyhow::Result<()> {
+    print!("{}", render(&outcomes, json)?);
+    let failed = outcomes
+        .iter()
+        .filter(|o| matches!(o.state, DnsState::Failed { .. }))
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} updates failed", outcomes.len());
+    }
+    Ok(())
+}
+
+fn render(outcomes: &[Outcome], json: bool) -> anyhow::Result<String> {
+    if json {
+        Ok(format!("{}\n", serde_json::to_string_pretty(outcomes)?))
+    } else {
+        let rows = outcomes.iter().map(|outcome| {
+            let (result, detail) = match &outcome.state {
+                DnsState::Succeed { addr, .. } => ("succeed", addr.to_string()),
+                DnsState::Failed { message, .. } => ("failed", message.replace('\n', " ")),
+            };
+            [
+                outcome.config.clone(),
+                outcome.family.map(|f| f.to_string()).unwrap_or("-".into()),
+                result.to_string(),
+                format!("{}ms", outcome.duration),
+                detail,
+            ]
+        });
+        Ok(table(
+            ["CONFIG", "FAMILY", "RESULT", ">TIME", "DETAIL"],
+            rows,
+        ))
+    }
+}
+
+fn print_table<const N: usize>(header: [&str; N], rows: impl Iterator<Item = [String; N]>) {
+    print!("{}", table(header, rows));
+}
+
+/// left aligned columns, the last one is not padded.
+/// a header starting with `>` is right aligned, as in format strings
+fn table<const N: usize>(header: [&str; N], rows: impl Iterator<Item = [String; N]>) -> String {
+    let right = header.map(|name| name.starts_with('>'));
+    let header = header.map(|name| name.trim_start_matches('>').to_string());
+    let rows: Vec<_> = std::iter::once(header).chain(rows).collect();
+    let mut widths = [0; N];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in &rows {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+            if i + 1 == N {
+                line.push_str(cell);
+            } else if right[i] {
+                line.push_str(&format!("{cell:>width$}  "));
+            } else {
+                line.push_str(&format!("{cell:width$}  "));
+            }
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// e.g. `nic eth0`
+fn describe(method: &Method) -> String {
+    match method {
+        Method::Api { endpoint } => format!("api {endpoint}"),
+        Method::Nic { interface } => format!("nic {interface}"),
+        Method::Cmd { command } => format!("cmd {command}"),
+        Method::Dns { server, query, .. } => format!("dns {query} @{server}"),
+        Method::Upnp { .. } => "upnp".to_string(),
+        Method::NatPmp { .. } => "natpmp".to_string(),
+    }
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize)]
+struct Detected {
+    /// none for a method given on the command line
+    config: Option<String>,
+    family: Family,
+    #[serde(flatten)]
+    method: Method,
+    #[serde(flatten)]
+    explained: Option<Explained>,
+    error: Option<String>,
+}
+
+pub async fn detect(args: DetectArgs) -> anyhow::Result<()> {
+    let targets = match &args.name {
+        Some(name) => {
+            let options = StoreOptions {
+                data_dir: args.location.data_dir.clone(),
+                read_only: true,
+                ..Default::default()
+            };
+            let store = StoreService::new(args.location.config(), options).await?;
+            let config = store
+                .get_dns_config(name)
+                .await
+                .with_context(|| format!("dns config not found: {name}"))?;
+            let targets: Vec<_> = [(config.ipv4, Family::Ipv4), (config.ipv6, Family::Ipv6)]
+                .into_iter()
+                .filter_map(|(cfg, family)| Some((cfg.filter(|cfg| cfg.enabled)?, family)))
+                .collect();
+            if targets.is_empty() {
+                bail!("dns config {name} has no enabled family");
+            }
+            targets
+        }
+        None => vec![(addr_config(&args)?, args.family)],
+    };
+    let mut results = Vec::with_capacity(targets.len());
+    for (config, family) in targets {
+        let (explained, error) = match detect::explain(&config, family).await {
+            Ok(explained) => (Some(explained), None),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+        results.push(Detected {
+            config: args.name.clone(),
+            family,
+            method: config.method,
+            explained,
+            error,
+        });
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for (i, detected) in results.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_detected(detected);
+        }
+    }
+    let failed = results
+        .iter()
+        .filter(|d| d.explained.as_ref().is_none_or(|e| e.chosen.is_none()))
+        .count();
+    if failed > 0 {
+        bail!(
+            "no address chosen for {failed} of {} methods",
+            results.len()
+        );
+    }
+    Ok(())
+}
+
+/// the method given on the command line, checked as it would be in a config
+fn addr_config(args: &DetectArgs) -> anyhow::Result<AddrConfig> {
+    let mut config = serde_json::Map::new();
+    config.insert("enabled".into(), true.into());
+    config.insert("method".into(), args.method.clone().into());
+    let fields = [
+        ("endpoint", &args.endpoint),
+        ("interface", &args.interface),
+        ("command", &args.command),
+        ("server", &args.server),
+        ("query", &args.query),
+        ("record", &args.record),
+        ("class", &args.class),
+        ("location", &args.upnp_location),
+        ("gateway", &args.gateway),
+        ("suffix", &args.suffix),
+    ];
+    for (field, value) in fields {
+        if let Some(value) = value {
+            config.insert(field.into(), value.clone().into());
+        }
+    }
+    let config: AddrConfig = serde_json::from_value(config.into())?;
+    let mut v = Validator::default();
+    v.scope(args.family.to_string(), |v| {
+        validate::check_addr(v, &config, args.family)
+    });
+    v.finish()?;
+    Ok(config)
+}
+
+fn print_detected(detected: &Detected) {
+    let method = describe(&detected.method);
+    match &detected.config {
+        Some(config) => println!("{config} {}: {method}", detected.family),
+        None => println!("{}: {method}", detected.family),
+    }
+    if let Some(error) = &detected.error {
+        println!("error: {error}");
+        return;
+    }
+    let Some(Explained { candidates, chosen }) = &detected.explained else {
+        return;
+    };
+    if candidates.is_empty() {
+        println!("no address reported");
+    } else {
+        let rows = candidates.iter().map(|candidate| {
+            // as `ip addr` shows them, e.g. `global temporary dynamic`
+            let scope = match &candidate.flags {
+                Some(flags) if !flags.is_empty() => {
+                    format!("{} {}", candidate.scope, flags.join(" "))
+                }
+                _ => candidate.scope.to_string(),
+            };
+            [
+                candidate.addr.to_string(),
+                scope,
+                candidate.skipped.unwrap_or("chosen").to_string(),
+            ]
+        });
+        print_table(["ADDRESS", "SCOPE", "NOTE"], rows);
+    }
+    match chosen {
+        Some(addr) => println!("chosen: {addr}"),
+        None => println!("chosen: none, the update fails with \"cannot find valid ip address\""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::testing::{inline_store, sample_config};
+
+    #[tokio::test]
+    async fn once_outcomes() {
+        let content = serde_json::json!({
+            "listen": "127.0.0.1:6789",
+            "ddns": [sample_config("missing")],
+        });
+        let store = inline_store("once", &content.to_string()).await;
+        let configs = store.list_dns_configs().await;
+        let outcomes = run_configs(&store, configs).await;
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes[0].family.is_none());
+
+        let output = render(&outcomes, false).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "CONFIG  FAMILY  RESULT  TIME  DETAIL",
+                "home    -       failed   0ms  provider not found: missing",
+            ]
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&outcomes, true).unwrap()).unwrap();
+        assert_eq!(json[0]["config"], "home");
+        assert_eq!(json[0]["kind"], "failed");
+        assert!(json[0].get("family").is_none());
+        // any failure makes the command exit with an error
+        let e = report(outcomes, true).unwrap_err();
+        assert_eq!(e.to_string(), "1 of 1 updates failed");
+
+        let outcomes = vec![Outcome {
+            config: "home".to_string(),
+            family: Some(Family::Ipv4),
+            state: DnsState::Succeed {
+                timestamp: 1,
+                addr: [192, 0, 2, 1].into(),
+            },
+            duration: 120,
+        }];
+        let output = render(&outcomes, false).unwrap();
+        assert!(output.ends_with("home    ipv4    succeed  120ms  192.0.2.1\n"));
+        assert!(report(outcomes, true).is_ok());
+    }
+}
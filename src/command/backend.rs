@@ -0,0 +1,540 @@
+use crate::{
+    cli::Location,
+    model::{DnsConfig, Provider, User, Webhook, validate::Validate},
+    provider::{self, CheckResult},
+    service::{
+        detect::DetectCache,
+        store::{self, StoreOptions, StoreService},
+        task::{self, Planned},
+        token::LocalToken,
+        webhook,
+    },
+};
+use anyhow::{Context, bail};
+use reqwest::{Client, Method};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// long enough for the webhook test
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// where the management commands apply their changes
+pub enum Backend {
+    /// the config file, no server is running
+    Local(StoreService),
+    /// the api of the server running with the config, so its tasks follow at once
+    Remote(Remote),
+}
+
+impl Backend {
+    /// the running server if its token file is found and it answers, the config file otherwise.
+    /// the config file is never created, a mistyped `-c` or `--data-dir` would go unnoticed
+    pub async fn open(location: &Location) -> anyhow::Result<Self> {
+        let path = location.config();
+        let token_file = store::data_file(&path, location.data_dir.as_deref(), "token");
+        if let Some(token) = LocalToken::read(&token_file).await
+            && let Some(remote) = Remote::connect(token).await
+        {
+            return Ok(Self::Remote(remote));
+        }
+        if !tokio::fs::try_exists(&path).await? {
+            bail!(
+                "config {} does not exist, check -c and --data-dir or start the server once to create it",
+                path.to_string_lossy()
+            );
+        }
+        let options = StoreOptions {
+            data_dir: location.data_dir.clone(),
+            ..Default::default()
+        };
+        Ok(Self::Local(StoreService::new(path, options).await?))
+    }
+
+    pub async fn list_dns(&self) -> anyhow::Result<Vec<DnsConfig>> {
+        match self {
+            Self::Local(store) => Ok(store.list_dns_configs().await),
+            Self::Remote(remote) => remote.get("/api/dns").await,
+        }
+    }
+
+    pub async fn get_dns(&self, name: &str) -> anyhow::Result<DnsConfig> {
+        let config = match self {
+            Self::Local(store) => store.get_dns_config(name).await,
+            Self::Remote(remote) => remote.get(&format!("/api/dns/{name}")).await?,
+        };
+        config.with_context(|| format!("dns config not found: {name}"))
+    }
+
+    pub async fn save_dns(&self, mut config: DnsConfig, replace: bool) -> anyhow::Result<()> {
+        match self {
+            Self::Local(store) => {
+                if let Some(stored) = store.get_dns_config(&config.name).await {
+                    if !replace {
+                        bail!("duplicate name: {}, use --force to replace it", config.name);
+                    }
+                    // only changed through pause/resume
+                    config.paused = stored.paused;
+                }
+                let interfaces = local_ip_address::list_afinet_netifas()?;
+                store.check_dns_config(&config, &interfaces).await?;
+                store.save_dns_config(&config).await
+            }
+            Self::Remote(remote) if replace => {
+                let path = format!("/api/dns/{}", config.name);
+                remote.send(Method::PUT, &path, Some(&config)).await
+            }
+            Self::Remote(remote) => remote.send(Method::POST, "/api/dns", Some(&config)).await,
+        }
+    }
+
+    pub async fn delete_dns(&self, name: &str) -> anyhow::Result<()> {
+        self.get_dns(name).await?;
+        match self {
+            Self::Local(store) => store.delete_dns_config(name).await,
+            Self::Remote(remote) => remote.delete(&format!("/api/dns/{name}")).await,
+        }
+    }
+
+    /// what running the config would change
+    pub async fn dry_run_dns(&self, name: &str) -> anyhow::Result<Vec<Planned>> {
+        let config = self.get_dns(name).await?;
+        match self {
+            Self::Local(store) => task::dry_run(&config, store, &DetectCache::default()).await,
+            Self::Remote(remote) => {
+                let path = "/api/dns/dry-run/plan";
+                let planned = remote.call(Method::POST, path, Some(&config)).await?;
+                Ok(planned.unwrap_or_default())
+            }
+        }
+    }
+
+    /// the secrets are redacted
+    pub async fn list_providers(&self) -> anyhow::Result<Vec<Provider>> {
+        match self {
+            Self::Local(store) => {
+                let mut providers = store.list_dns_providers().await;
+                providers.iter_mut().for_each(Provider::redact);
+                Ok(providers)
+            }
+            Self::Remote(remote) => remote.get("/api/provider/list").await,
+        }
+    }
+
+    pub async fn save_provider(&self, mut provider: Provider, replace: bool) -> anyhow::Result<()> {
+        match self {
+            Self::Local(store) => {
+                if let Some(stored) = store.get_dns_provider(&provider.name).await {
+                    if !replace {
+                        bail!(
+                            "duplicate name: {}, use --force to replace it",
+                            provider.name
+                        );
+                    }
+                    // a file printed by `provider list` has the secrets redacted
+                    provider.restore(&stored);
+                }
+                provider.validate()?;
+                store.save_dns_provider(&provider).await
+            }
+            Self::Remote(remote) if replace => {
+                let path = format!("/api/provider/{}", provider.name);
+                remote.send(Method::PUT, &path, Some(&provider)).await
+            }
+            Self::Remote(remote) => {
+                let path = "/api/provider";
+                remote.send(Method::POST, path, Some(&provider)).await
+            }
+        }
+    }
+
+    pub async fn delete_provider(&self, name: &str) -> anyhow::Result<()> {
+        if !self.list_providers().await?.iter().any(|p| p.name == name) {
+            bail!("provider not found: {name}");
+        }
+        match self {
+            Self::Local(store) => store.delete_dns_provider(name).await,
+            Self::Remote(remote) => remote.delete(&format!("/api/provider/{name}")).await,
+        }
+    }
+
+    pub async fn test_provider(&self, name: &str) -> anyhow::Result<CheckResult> {
+        let provider = match self {
+            Self::Local(store) => store.get_dns_provider(name).await,
+            // the server restores the redacted secrets
+            Self::Remote(_) => {
+                let providers = self.list_providers().await?;
+                providers.into_iter().find(|p| p.name == name)
+            }
+        };
+        let provider = provider.with_context(|| format!("provider not found: {name}"))?;
+        match self {
+            Self::Local(_) => {
+                provider.validate()?;
+                Ok(provider::check(&provider).await)
+            }
+            Self::Remote(remote) => {
+                let result = remote
+                    .call(Method::POST, "/api/provider/run/test", Some(&provider))
+                    .await?;
+                result.context("empty reply")
+            }
+        }
+    }
+
+    pub async fn list_webhooks(&self) -> anyhow::Result<Vec<Webhook>> {
+        match self {
+            Self::Local(store) => Ok(store.list_webhooks().await),
+            Self::Remote(remote) => remote.get("/api/webhook/list").await,
+        }
+    }
+
+    pub async fn get_webhook(&self, name: &str) -> anyhow::Result<Webhook> {
+        let webhooks = self.list_webhooks().await?;
+        let webhook = webhooks.into_iter().find(|w| w.name == name);
+        webhook.with_context(|| format!("webhook not found: {name}"))
+    }
+
+    pub async fn save_webhook(&self, webhook: Webhook, replace: bool) -> anyhow::Result<()> {
+        match self {
+            Self::Local(store) => {
+                if !replace && store.get_webhook(&webhook.name).await.is_some() {
+                    bail!(
+                        "duplicate name: {}, use --force to replace it",
+                        webhook.name
+                    );
+                }
+                webhook.validate()?;
+                store.save_webhook(&webhook).await
+            }
+            Self::Remote(remote) => {
+                if !replace && self.get_webhook(&webhook.name).await.is_ok() {
+                    bail!(
+                        "duplicate name: {}, use --force to replace it",
+                        webhook.name
+                    );
+                }
+                remote
+                    .send(Method::POST, "/api/webhook", Some(&webhook))
+                    .await
+            }
+        }
+    }
+
+    pub async fn delete_webhook(&self, name: &str) -> anyhow::Result<()> {
+        self.get_webhook(name).await?;
+        match self {
+            Self::Local(store) => store.delete_webhook(name).await,
+            Self::Remote(remote) => remote.delete(&format!("/api/webhook/{name}")).await,
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        matches!(self, Self::Remote(_))
+    }
+
+    /// the running server does not tell its user
+    pub async fn username(&self) -> Option<String> {
+        match self {
+            Self::Local(store) => store.get_user().await.map(|user| user.username),
+            Self::Remote(_) => None,
+        }
+    }
+
+    /// the running server checks the `current` password, as for the web ui
+    pub async fn save_user(&self, user: User, current: Option<String>) -> anyhow::Result<()> {
+        match self {
+            Self::Local(store) => store.save_user(user).await,
+            Self::Remote(remote) => {
+                let password = current.context("the current password is required")?;
+                let update = UpdateUser { password, user };
+                remote
+                    .send(Method::POST, "/api/user/update", Some(&update))
+                    .await
+            }
+        }
+    }
+
+    /// the response of the webhook
+    pub async fn test_webhook(&self, name: &str) -> anyhow::Result<String> {
+        let webhook = self.get_webhook(name).await?;
+        match self {
+            Self::Local(_) => webhook::notify(&webhook::sample_config(name), &webhook).await,
+            Self::Remote(remote) => {
+                let path = "/api/webhook/run/test";
+                let data: Option<TestResult> =
+                    remote.call(Method::POST, path, Some(&webhook)).await?;
+                Ok(data.map(|result| result.data).unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// see `api::user::UpdateUser`
+#[derive(Serialize)]
+struct UpdateUser {
+    password: String,
+    user: User,
+}
+
+#[derive(Deserialize)]
+struct TestResult {
+    data: String,
+}
+
+/// the api reply, see `api::ApiResult`
+#[derive(Deserialize)]
+struct Reply<T> {
+    code: u32,
+    message: Option<String>,
+    data: Option<T>,
+}
+
+pub struct Remote {
+    url: String,
+    token: String,
+    client: Client,
+}
+
+impl Remote {
+    /// none if no server answers at the url of the token
+    async fn connect(LocalToken { url, token }: LocalToken) -> Option<Self> {
+        let client = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .ok()?;
+        let remote = Self { url, token, client };
+        // a stale token file is left when the server is killed
+        let info: Option<serde_json::Value> = remote
+            .call::<(), _>(Method::GET, "/api/sys/info", None)
+            .await
+            .ok()?;
+        info.map(|_| remote)
+    }
+
+    pub async fn call<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> anyhow::Result<Option<T>> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}{path}", self.url))
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("request {}", self.url))?;
+        // the api replies its errors with a code, other statuses come from the router,
+        // e.g. a server of another version
+        let status = response.status();
+        if !status.is_success() {
+            bail!("{method} {path}: {status}");
+        }
+        let reply: Reply<T> = response.json().await?;
+        if reply.code != 0 {
+            bail!(
+                "{}",
+                reply
+                    .message
+                    .unwrap_or_else(|| format!("error code {}", reply.code))
+            );
+        }
+        Ok(reply.data)
+    }
+
+    async fn get<T: DeserializeOwned + Default>(&self, path: &str) -> anyhow::Result<T> {
+        let data = self.call::<(), T>(Method::GET, path, None).await?;
+        Ok(data.unwrap_or_default())
+    }
+
+    pub async fn send<B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> anyhow::Result<()> {
+        self.call::<B, serde_json::Value>(method, path, body)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> anyhow::Result<()> {
+        self.send::<()>(Method::DELETE, path, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::testing::{sample_config, temp_dir};
+    use axum::{
+        Json, Router,
+        http::HeaderMap,
+        routing::{get, post},
+    };
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    /// a server holding one dns config, only answering the local token
+    async fn server() -> String {
+        let authorized = |headers: &HeaderMap| headers["authorization"] == "Bearer local";
+        let router = Router::new()
+            .route(
+                "/api/sys/info",
+                get(|| async { Json(json!({ "code": 0, "data": {} })) }),
+            )
+            .route(
+                "/api/dns",
+                get(move |headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        return Json(json!({ "code": 401, "message": "unauthorized" }));
+                    }
+                    let mut config = sample_config("cf");
+                    config["name"] = json!("remote");
+                    Json(json!({ "code": 0, "data": [config] }))
+                }),
+            )
+            .route(
+                "/api/user/update",
+                post(|Json(update): Json<Value>| async move {
+                    if update["password"] != "old" {
+                        return Json(json!({ "code": 401, "message": "invalid password" }));
+                    }
+                    assert_eq!(
+                        update["user"],
+                        json!({ "username": "admin", "password": "new" })
+                    );
+                    Json(json!({ "code": 0 }))
+                }),
+            )
+            .route(
+                "/api/dns/{name}",
+                get(|| async { Json(json!({ "code": 500, "message": "boom" })) }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn location(dir: &std::path::Path) -> Location {
+        let mut location = Location::default();
+        location.data_dir = Some(dir.to_path_buf());
+        location
+    }
+
+    #[tokio::test]
+    async fn open_remote() {
+        let dir = temp_dir("backend").await;
+        let location = location(&dir);
+        let e = Backend::open(&location).await.err().unwrap();
+        assert!(e.to_string().contains("does not exist"));
+        assert!(!tokio::fs::try_exists(location.config()).await.unwrap());
+        StoreService::new(location.config(), Default::default())
+            .await
+            .unwrap();
+        let token_file = dir.join("ddns-rs.token");
+        let token = |url: &str, token: &str| LocalToken {
+            url: url.to_string(),
+            token: token.to_string(),
+        };
+
+        let backend = Backend::open(&location).await.unwrap();
+        assert!(matches!(backend, Backend::Local(_)));
+        // left by a server that was killed
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stale = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        token(&stale, "local").write(&token_file).await.unwrap();
+        let backend = Backend::open(&location).await.unwrap();
+        assert!(matches!(backend, Backend::Local(_)));
+
+        let url = server().await;
+        token(&url, "local").write(&token_file).await.unwrap();
+        let backend = Backend::open(&location).await.unwrap();
+        assert!(matches!(backend, Backend::Remote(_)));
+        let configs = backend.list_dns().await.unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].name, "remote");
+        let e = backend.get_dns("remote").await.err().unwrap();
+        assert_eq!(e.to_string(), "boom");
+        let Backend::Remote(remote) = &backend else {
+            unreachable!()
+        };
+        let e = remote.send::<()>(Method::PUT, "/api/dns", None).await;
+        assert_eq!(
+            e.unwrap_err().to_string(),
+            "PUT /api/dns: 405 Method Not Allowed"
+        );
+        // the config file is not written behind the server
+        assert!(backend.username().await.is_none());
+        let user = || User {
+            username: "admin".to_string(),
+            password: "new".to_string(),
+        };
+        assert!(backend.save_user(user(), None).await.is_err());
+        let e = backend.save_user(user(), Some("wrong".into())).await;
+        assert_eq!(e.unwrap_err().to_string(), "invalid password");
+        backend.save_user(user(), Some("old".into())).await.unwrap();
+        let config = tokio::fs::read_to_string(location.config()).await.unwrap();
+        assert!(!config.contains("admin"));
+
+        token(&url, "expired").write(&token_file).await.unwrap();
+        let backend = Backend::open(&location).await.unwrap();
+        let e = backend.list_dns().await.err().unwrap();
+        assert_eq!(e.to_string(), "unauthorized");
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn save_local() {
+        let dir = temp_dir("local").await;
+        let location = location(&dir);
+        StoreService::new(location.config(), Default::default())
+            .await
+            .unwrap();
+        let backend = Backend::open(&location).await.unwrap();
+        let provider = json!({ "name": "cf", "kind": "cloudflare", "apiKey": "token" });
+        let provider: Provider = serde_json::from_value(provider).unwrap();
+        backend.save_provider(provider, false).await.unwrap();
+        let config = |provider: &str| -> DnsConfig {
+            serde_json::from_value(sample_config(provider)).unwrap()
+        };
+
+        let e = backend
+            .save_dns(config("missing"), false)
+            .await
+            .unwrap_err();
+        assert!(format!("{e:#}").contains("provider not found: missing"));
+        backend.save_dns(config("cf"), false).await.unwrap();
+        let e = backend.save_dns(config("cf"), false).await.unwrap_err();
+        assert!(e.to_string().starts_with("duplicate name: home"));
+        let Backend::Local(store) = &backend else {
+            unreachable!()
+        };
+        let mut paused = config("cf");
+        paused.paused = true;
+        store.save_dns_config(&paused).await.unwrap();
+        // only changed through pause/resume
+        backend.save_dns(config("cf"), true).await.unwrap();
+        assert!(backend.get_dns("home").await.unwrap().paused);
+
+        let providers = backend.list_providers().await.unwrap();
+        assert!(!serde_json::to_string(&providers).unwrap().contains("token"));
+
+        assert!(backend.username().await.is_none());
+        let user = User {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+        };
+        backend.save_user(user, None).await.unwrap();
+        assert_eq!(backend.username().await.unwrap(), "admin");
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
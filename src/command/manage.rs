@@ -0,0 +1,328 @@
+use super::{backend::Backend, describe, print_table, report, run_configs};
+use crate::{
+    cli::{
+        AddArgs, DnsCommands, ListArgs, NameArgs, PasswdArgs, ProviderCommands, RunDnsArgs,
+        ShowArgs, UserCommands, WebhookAddArgs, WebhookCommands,
+    },
+    model::{AddrConfig, DnsConfig, Provider, User, Webhook},
+    provider::Change,
+    service::{store::format::Format, task::Planned},
+};
+use anyhow::{Context, bail};
+use serde::de::DeserializeOwned;
+use std::{
+    io::{IsTerminal, Read, Write},
+    path::Path,
+};
+
+pub async fn dns(command: DnsCommands) -> anyhow::Result<()> {
+    match command {
+        DnsCommands::List(ListArgs { location, json }) => {
+            let configs = Backend::open(&location).await?.list_dns().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&configs)?);
+                return Ok(());
+            }
+            let rows = configs.iter().map(|config| {
+                [
+                    config.name.clone(),
+                    config.domain.to_string(),
+                    config.provider.clone(),
+                    method(&config.ipv4),
+                    method(&config.ipv6),
+                    if config.paused { "paused" } else { "active" }.to_string(),
+                ]
+            });
+            print_table(
+                ["NAME", "DOMAIN", "PROVIDER", "IPV4", "IPV6", "STATUS"],
+                rows,
+            );
+        }
+        DnsCommands::Show(ShowArgs {
+            location,
+            name,
+            json,
+        }) => {
+            let config = Backend::open(&location).await?.get_dns(&name).await?;
+            let format = if json { Format::Json } else { Format::Yaml };
+            println!("{}", format.to_string(&config)?.trim_end());
+        }
+        DnsCommands::Add(AddArgs {
+            location,
+            file,
+            force,
+        }) => {
+            let config: DnsConfig = read_document(file.as_deref())?;
+            let name = config.name.clone();
+            Backend::open(&location)
+                .await?
+                .save_dns(config, force)
+                .await?;
+            println!("saved dns config {name}");
+        }
+        DnsCommands::Rm(NameArgs { location, name }) => {
+            Backend::open(&location).await?.delete_dns(&name).await?;
+            println!("removed dns config {name}");
+        }
+        DnsCommands::Run(RunDnsArgs {
+            location,
+            name,
+            dry_run: true,
+            json,
+        }) => {
+            let planned = Backend::open(&location).await?.dry_run_dns(&name).await?;
+            print_planned(&planned, json)?;
+        }
+        DnsCommands::Run(RunDnsArgs { location, name, .. }) => {
+            match Backend::open(&location).await? {
+                Backend::Local(store) => {
+                    let config = store
+                        .get_dns_config(&name)
+                        .await
+                        .with_context(|| format!("dns config not found: {name}"))?;
+                    report(run_configs(&store, vec![config]).await, false)?;
+                }
+                Backend::Remote(remote) => {
+                    let path = format!("/api/dns/run/{name}");
+                    remote.send::<()>(reqwest::Method::PUT, &path, None).await?;
+                    println!("dns config {name} is updating, see `ddns-rs dns list` or the log");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// fails if a family could not be planned
+fn print_planned(planned: &[Planned], json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(planned)?);
+    } else {
+        let rows = planned.iter().flat_map(|plan| {
+            let family = plan.family.to_string();
+            let addr = plan.addr.map(|addr| addr.to_string()).unwrap_or("-".into());
+            let mut rows: Vec<_> = plan
+                .changes
+                .iter()
+                .map(|change| {
+                    let (action, name, kind, change) = match change {
+                        Change::Create { name, kind, value } => {
+                            ("create", name, kind, value.clone())
+                        }
+                        Change::Update {
+                            name,
+                            kind,
+                            from,
+                            to,
+                        } => ("update", name, kind, format!("{from} -> {to}")),
+                        Change::Unchanged { name, kind, value } => {
+                            ("unchanged", name, kind, value.clone())
+                        }
+                    };
+                    [
+                        family.clone(),
+                        addr.clone(),
+                        action.to_string(),
+                        format!("{name} {kind}"),
+                        change,
+                    ]
+                })
+                .collect();
+            if let Some(message) = &plan.message {
+                rows.push([
+                    family.clone(),
+                    addr.clone(),
+                    "failed".to_string(),
+                    "-".to_string(),
+                    message.clone(),
+                ]);
+            }
+            rows
+        });
+        print_table(["FAMILY", "ADDRESS", "ACTION", "RECORD", "VALUE"], rows);
+    }
+    let failed = planned.iter().filter(|plan| plan.error.is_some()).count();
+    if failed > 0 {
+        bail!("{failed} of {} families failed", planned.len());
+    }
+    Ok(())
+}
+
+/// the method of an enabled family
+fn method(config: &Option<AddrConfig>) -> String {
+    match config.as_ref().filter(|config| config.enabled) {
+        Some(config) => describe(&config.method),
+        None => "-".to_string(),
+    }
+}
+
+pub async fn provider(command: ProviderCommands) -> anyhow::Result<()> {
+    match command {
+        ProviderCommands::List(ListArgs { location, json }) => {
+            let providers = Backend::open(&location).await?.list_providers().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&providers)?);
+                return Ok(());
+            }
+            let rows = providers
+                .iter()
+                .map(|provider| [provider.name.clone(), provider.config.ty().to_string()]);
+            print_table(["NAME", "KIND"], rows);
+        }
+        ProviderCommands::Add(AddArgs {
+            location,
+            file,
+            force,
+        }) => {
+            let provider: Provider = read_document(file.as_deref())?;
+            let name = provider.name.clone();
+            Backend::open(&location)
+                .await?
+                .save_provider(provider, force)
+                .await?;
+            println!("saved provider {name}");
+        }
+        ProviderCommands::Rm(NameArgs { location, name }) => {
+            Backend::open(&location)
+                .await?
+                .delete_provider(&name)
+                .await?;
+            println!("removed provider {name}");
+        }
+        ProviderCommands::Test(ShowArgs {
+            location,
+            name,
+            json,
+        }) => {
+            let result = Backend::open(&location).await?.test_provider(&name).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else if result.ok {
+                let rows = result.zones.iter().map(|zone| {
+                    let status = zone.status.clone().unwrap_or("-".into());
+                    [zone.name.clone(), zone.id.clone(), status]
+                });
+                print_table(["ZONE", "ID", "STATUS"], rows);
+            }
+            match result.message {
+                Some(message) if !result.ok => bail!("{message}"),
+                Some(message) if !json => eprintln!("warning: {message}"),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn webhook(command: WebhookCommands) -> anyhow::Result<()> {
+    match command {
+        WebhookCommands::List(ListArgs { location, json }) => {
+            let webhooks = Backend::open(&location).await?.list_webhooks().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&webhooks)?);
+                return Ok(());
+            }
+            let rows = webhooks.iter().map(|webhook| {
+                let request = webhook.value.lines().next().unwrap_or_default();
+                [webhook.name.clone(), request.to_string()]
+            });
+            print_table(["NAME", "REQUEST"], rows);
+        }
+        WebhookCommands::Add(WebhookAddArgs {
+            location,
+            name,
+            file,
+            force,
+        }) => {
+            let value = read_input(file.as_deref())?;
+            let webhook = Webhook {
+                name: name.clone(),
+                value,
+            };
+            Backend::open(&location)
+                .await?
+                .save_webhook(webhook, force)
+                .await?;
+            println!("saved webhook {name}");
+        }
+        WebhookCommands::Rm(NameArgs { location, name }) => {
+            Backend::open(&location)
+                .await?
+                .delete_webhook(&name)
+                .await?;
+            println!("removed webhook {name}");
+        }
+        WebhookCommands::Test(NameArgs { location, name }) => {
+            let response = Backend::open(&location).await?.test_webhook(&name).await?;
+            println!("{response}");
+        }
+    }
+    Ok(())
+}
+
+pub async fn user(command: UserCommands) -> anyhow::Result<()> {
+    match command {
+        UserCommands::Passwd(PasswdArgs { location, username }) => {
+            let backend = Backend::open(&location).await?;
+            let Some(username) = username.or(backend.username().await) else {
+                if backend.is_remote() {
+                    bail!("the running server does not tell its user, give it with --username");
+                }
+                bail!("no user yet, set one with --username");
+            };
+            // the running server asks for the current password, as the web ui does
+            let current = if backend.is_remote() {
+                Some(read_line("current password: ")?)
+            } else {
+                None
+            };
+            let password = read_password()?;
+            let user = User { username, password };
+            backend.save_user(user, current).await?;
+            println!("password updated");
+        }
+    }
+    Ok(())
+}
+
+/// the prompt is only shown on a terminal
+fn read_line(prompt: &str) -> anyhow::Result<String> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("{prompt}");
+        std::io::stderr().flush()?;
+    }
+    let mut line = String::new();
+    stdin.read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_password() -> anyhow::Result<String> {
+    let password = read_line("new password: ")?;
+    if password.is_empty() {
+        bail!("password required");
+    }
+    if std::io::stdin().is_terminal() && read_line("repeat password: ")? != password {
+        bail!("passwords do not match");
+    }
+    Ok(password)
+}
+
+/// a document in the format of the file extension, yaml (or json) from stdin
+fn read_document<T: DeserializeOwned>(file: Option<&Path>) -> anyhow::Result<T> {
+    let file = file.filter(|path| *path != Path::new("-"));
+    let format = file.map(Format::from_path).unwrap_or(Format::Yaml);
+    format.parse(&read_input(file)?)
+}
+
+fn read_input(file: Option<&Path>) -> anyhow::Result<String> {
+    match file {
+        Some(path) if path != Path::new("-") => std::fs::read_to_string(path)
+            .with_context(|| format!("read {}", path.to_string_lossy())),
+        _ => {
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content)?;
+            Ok(content)
+        }
+    }
+}
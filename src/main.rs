@@ -5,6 +5,7 @@ use tracing::error;
 
 mod api;
 mod cli;
+mod command;
 pub mod model;
 mod provider;
 mod server;
@@ -14,6 +15,7 @@ mod website;
 shadow!(build);
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
+    service::schedule::init_local_zone();
     match start().await {
         Ok(_) => {}
         Err(e) => error!("{:#}", e),
@@ -23,10 +25,17 @@ async fn main() -> anyhow::Result<()> {
 
 async fn start() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    match cli.command.unwrap_or_else(Default::default) {
+    match cli.command.unwrap_or(Commands::Run(cli.run)) {
         Commands::Run(args) => {
             server::run(args).await?;
         }
+        Commands::Config(command) => command::exit_on_error(command::config(command).await),
+        Commands::Once(args) => command::exit_on_error(command::once(args).await),
+        Commands::Dns(command) => command::exit_on_error(command::dns(command).await),
+        Commands::Provider(command) => command::exit_on_error(command::provider(command).await),
+        Commands::Webhook(command) => command::exit_on_error(command::webhook(command).await),
+        Commands::User(command) => command::exit_on_error(command::user(command).await),
+        Commands::Detect(args) => command::exit_on_error(command::detect(*args).await),
     }
     Ok(())
 }
@@ -1,13 +1,21 @@
 use crate::provider::{AliyunConfig, CloudflareConfig, TencentConfig};
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
+    collections::VecDeque,
     fmt::{Display, Formatter},
     net::IpAddr,
+    str::FromStr,
 };
 
+pub mod validate;
+
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DdnsConfig {
+    /// upgraded on load by the migrations in the store, 0 when missing
+    #[serde(default)]
+    pub version: u64,
     pub listen: String,
     pub user: Option<User>,
     #[serde(default)]
@@ -41,8 +49,36 @@ pub struct DnsConfig {
     pub ipv6: Option<AddrConfig>,
     pub provider: String,
     pub webhook: Option<String>,
+    pub schedule: Option<Schedule>,
+    /// keeps the config without updating it
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub paused: bool,
+    /// whether the task is restarted after a panic, `onFailure` by default
+    pub restart: Option<RestartPolicy>,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartPolicy {
+    Never,
+    /// after a panic, a task only stops when its config is deleted.
+    /// `always` of older configs meant the same
+    #[default]
+    #[serde(alias = "always")]
+    OnFailure,
+}
+/// replaces the fixed `interval` when `cron` is set, times are local
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Schedule {
+    /// `minute hour day-of-month month day-of-week`
+    pub cron: Option<String>,
+    /// `HH:MM-HH:MM`, no scheduled update runs in between
+    pub quiet: Option<String>,
+    /// random delay in seconds added to every run
+    pub jitter: Option<u64>,
 }
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Family {
     Ipv4,
@@ -56,6 +92,17 @@ impl Display for Family {
         })
     }
 }
+impl FromStr for Family {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ipv4" => Ok(Family::Ipv4),
+            "ipv6" => Ok(Family::Ipv6),
+            _ => Err(format!("invalid family: {value}, expected ipv4 or ipv6")),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct User {
@@ -63,7 +110,7 @@ pub struct User {
     pub password: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 #[serde(deny_unknown_fields)]
 #[serde(tag = "method")]
@@ -80,6 +127,47 @@ pub enum Method {
         #[serde(default)]
         command: String,
     },
+    Dns {
+        #[serde(default)]
+        server: String,
+        #[serde(default)]
+        query: String,
+        #[serde(default)]
+        record: RecordType,
+        #[serde(default)]
+        class: QueryClass,
+    },
+    Upnp {
+        /// device description url of the gateway, discovered with SSDP when empty
+        #[serde(default)]
+        location: String,
+    },
+    NatPmp {
+        /// gateway address, the default route gateway when empty
+        #[serde(default)]
+        gateway: String,
+    },
+}
+
+/// record type asked by [`Method::Dns`]
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordType {
+    /// `A` for ipv4, `AAAA` for ipv6 (e.g. `myip.opendns.com`)
+    #[default]
+    Addr,
+    /// address carried in a TXT record (e.g. `o-o.myaddr.l.google.com`)
+    Txt,
+}
+
+/// query class used by [`Method::Dns`]
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryClass {
+    #[default]
+    In,
+    /// CHAOS class, required by `whoami.cloudflare`
+    Ch,
 }
 
 #[serde_with::skip_serializing_none]
@@ -88,14 +176,157 @@ pub struct AddrConfig {
     pub enabled: bool,
     #[serde(flatten)]
     pub method: Method,
+    pub suffix: Option<Suffix>,
+    /// kept in the state file, only read from configs written by older versions
+    #[serde(default, skip_serializing)]
     pub state: Option<DnsState>,
 }
 
+/// interface identifier appended to the detected prefix, e.g. `::10/64`.
+/// the prefix length defaults to 64 for ipv6.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Suffix {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl FromStr for Suffix {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|e| format!("invalid suffix {value}: {e}"))?;
+        let (prefix, max) = match (addr, prefix) {
+            (IpAddr::V6(_), None) => (64, 128),
+            (IpAddr::V4(_), None) => return Err(format!("prefix length required: {value}")),
+            (addr, Some(prefix)) => {
+                let prefix = prefix
+                    .parse()
+                    .map_err(|e| format!("invalid prefix length {value}: {e}"))?;
+                (prefix, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        };
+        if prefix > max {
+            return Err(format!("prefix length out of range: {value}"));
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Suffix {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Suffix> for String {
+    fn from(value: Suffix) -> Self {
+        format!("{}/{}", value.addr, value.prefix)
+    }
+}
+
+/// what the task of a config last did, persisted apart from the config
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RuntimeState {
+    pub ipv4: Option<DnsState>,
+    pub ipv6: Option<DnsState>,
+    /// successful updates of both families
+    #[serde(default)]
+    pub succeeded: u64,
+    #[serde(default)]
+    pub failed: u64,
+    /// address changes and outages, oldest first
+    #[serde(default)]
+    #[serde(skip_serializing_if = "VecDeque::is_empty")]
+    pub history: VecDeque<HistoryEntry>,
+}
+
+/// an update whose result differs from the previous one of the same family
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub family: Family,
+    /// the last published address
+    pub old: Option<IpAddr>,
+    /// none if the update failed
+    pub addr: Option<IpAddr>,
+    /// milliseconds taken by detection and update
+    pub duration: u64,
+    pub error: Option<ErrorKind>,
+    pub message: Option<String>,
+}
+
+impl RuntimeState {
+    pub fn get(&self, family: Family) -> Option<&DnsState> {
+        match family {
+            Family::Ipv4 => self.ipv4.as_ref(),
+            Family::Ipv6 => self.ipv6.as_ref(),
+        }
+    }
+
+    pub fn set(&mut self, family: Family, state: DnsState) {
+        match family {
+            Family::Ipv4 => self.ipv4 = Some(state),
+            Family::Ipv6 => self.ipv6 = Some(state),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase", tag = "kind")]
 pub enum DnsState {
-    Succeed { timestamp: u64, addr: IpAddr },
-    Failed { timestamp: u64, message: String },
+    Succeed {
+        timestamp: u64,
+        addr: IpAddr,
+    },
+    Failed {
+        timestamp: u64,
+        message: String,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorKind>,
+    },
+}
+
+/// why an update failed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// the address could not be detected
+    Detect,
+    Auth,
+    NotFound,
+    NoZone,
+    RateLimited,
+    Conflict,
+    Transient,
+    Invalid,
+    Unknown,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Detect => "detect",
+            ErrorKind::Auth => "auth",
+            ErrorKind::NotFound => "notFound",
+            ErrorKind::NoZone => "noZone",
+            ErrorKind::RateLimited => "rateLimited",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::Transient => "transient",
+            ErrorKind::Invalid => "invalid",
+            ErrorKind::Unknown => "unknown",
+        })
+    }
 }
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Provider {
@@ -119,6 +350,87 @@ impl RealProvider {
             Self::Aliyun(_) => "aliyun",
         }
     }
+
+    pub fn secrets_mut(&mut self) -> Vec<&mut Secret> {
+        match self {
+            Self::Tencent(config) => config.secrets_mut(),
+            Self::Cloudflare(config) => config.secrets_mut(),
+            Self::Aliyun(config) => config.secrets_mut(),
+        }
+    }
+}
+
+impl Provider {
+    /// hides the plain secrets from the api, references are kept
+    pub fn redact(&mut self) {
+        for secret in self.config.secrets_mut() {
+            if secret.is_plain() {
+                *secret = Secret(REDACTED.to_string());
+            }
+        }
+    }
+
+    /// secrets sent back redacted keep the stored values
+    pub fn restore(&mut self, stored: &Provider) {
+        if self.config.ty() != stored.config.ty() {
+            return;
+        }
+        let mut stored = stored.config.clone();
+        for (secret, stored) in self
+            .config
+            .secrets_mut()
+            .into_iter()
+            .zip(stored.secrets_mut())
+        {
+            if secret.0 == REDACTED {
+                *secret = stored.clone();
+            }
+        }
+    }
+}
+
+/// what the api returns instead of a secret
+pub const REDACTED: &str = "******";
+
+/// a credential stored in the config, `env:NAME` or `file:PATH` are read when used,
+/// `enc:` values are encrypted by the store with its key file
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl Secret {
+    /// not a reference, nor encrypted
+    pub fn is_plain(&self) -> bool {
+        !["env:", "file:", "enc:"]
+            .iter()
+            .any(|prefix| self.0.starts_with(prefix))
+    }
+
+    /// the value, references are read on every call, so clients resolve it once
+    pub async fn resolve(&self) -> anyhow::Result<Cow<'_, str>> {
+        if let Some(name) = self.0.strip_prefix("env:") {
+            let value = std::env::var(name)
+                .map_err(|e| anyhow::anyhow!("read secret from env {name}: {e}"))?;
+            return Ok(Cow::Owned(value));
+        }
+        if let Some(path) = self.0.strip_prefix("file:") {
+            let value = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| anyhow::anyhow!("read secret from {path}: {e}"))?;
+            // files written by `echo` end with a newline
+            return Ok(Cow::Owned(value.trim_end().to_string()));
+        }
+        if self.0.starts_with("enc:") {
+            anyhow::bail!("secret is encrypted, the key file is missing");
+        }
+        Ok(Cow::Borrowed(&self.0))
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -129,7 +441,7 @@ pub struct Webhook {
 
 #[cfg(test)]
 mod tests {
-    use crate::model::DnsConfig;
+    use crate::model::{DnsConfig, Provider, REDACTED, RestartPolicy, Secret};
     use serde_json::json;
 
     #[test]
@@ -150,8 +462,49 @@ mod tests {
                 "endpoint": "",
             },
             "provider": "",
-            "webhook": ""
+            "webhook": "",
+            "restart": "always"
         });
-        serde_json::from_value::<DnsConfig>(data).unwrap();
+        let config = serde_json::from_value::<DnsConfig>(data).unwrap();
+        assert_eq!(config.restart, Some(RestartPolicy::OnFailure));
+    }
+
+    #[tokio::test]
+    async fn secret() {
+        let path = std::env::temp_dir().join(format!("ddns-rs-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let secret = Secret(format!("file:{}", path.to_string_lossy()));
+        assert_eq!(secret.resolve().await.unwrap(), "from-file");
+        std::fs::remove_file(&path).unwrap();
+        assert!(secret.resolve().await.is_err());
+        assert!(
+            Secret("env:DDNS_RS_TEST_MISSING".to_string())
+                .resolve()
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            Secret("plain".to_string()).resolve().await.unwrap(),
+            "plain"
+        );
+
+        let provider = |key: &str| -> Provider {
+            serde_json::from_value(json!({ "name": "cf", "kind": "cloudflare", "apiKey": key }))
+                .unwrap()
+        };
+        let stored = provider("token");
+        let mut redacted = stored.clone();
+        redacted.redact();
+        assert_eq!(json!(redacted)["apiKey"], REDACTED);
+        let mut reference = provider("env:CF_TOKEN");
+        reference.redact();
+        assert_eq!(json!(reference)["apiKey"], "env:CF_TOKEN");
+
+        // an unchanged secret keeps the stored value, a new one replaces it
+        redacted.restore(&stored);
+        assert_eq!(json!(redacted)["apiKey"], "token");
+        let mut changed = provider("new");
+        changed.restore(&stored);
+        assert_eq!(json!(changed)["apiKey"], "new");
     }
 }
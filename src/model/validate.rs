@@ -0,0 +1,345 @@
+use crate::{
+    model::{
+        AddrConfig, DdnsConfig, DnsConfig, Domain, Family, Method, Provider, REDACTED,
+        RealProvider, Secret, Webhook,
+    },
+    service::{
+        schedule::{Cron, Quiet},
+        webhook,
+    },
+};
+use serde::Serialize;
+use std::{collections::HashSet, fmt, net::IpAddr, str::FromStr};
+use thiserror::Error;
+
+/// shorter intervals only hit the rate limits of the providers
+pub const MIN_INTERVAL: u64 = 5;
+pub const MAX_INTERVAL: u64 = 24 * 60 * 60;
+
+/// a rejected field, `field` is its path in the json, e.g. `ipv4.interface`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Error, Debug)]
+pub struct ValidationError(pub Vec<FieldError>);
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, FieldError { field, message }) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{field}: {message}")?;
+        }
+        Ok(())
+    }
+}
+
+/// collects the errors of every field instead of stopping at the first one
+#[derive(Default)]
+pub struct Validator {
+    path: Vec<String>,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn error(&mut self, field: &str, message: impl Into<String>) {
+        let mut path = self.path.join(".");
+        if !field.is_empty() {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(field);
+        }
+        self.errors.push(FieldError {
+            field: path,
+            message: message.into(),
+        });
+    }
+
+    /// errors reported by `f` are prefixed by `name`
+    pub fn scope(&mut self, name: impl Into<String>, f: impl FnOnce(&mut Self)) {
+        self.path.push(name.into());
+        f(self);
+        self.path.pop();
+    }
+
+    pub fn required(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.error(field, "required");
+        }
+    }
+
+    pub fn secret(&mut self, field: &str, secret: &Secret) {
+        let value = &secret.0;
+        if let Some(name) = value.strip_prefix("env:") {
+            if name.is_empty() {
+                self.error(field, "environment variable name required");
+            }
+        } else if let Some(path) = value.strip_prefix("file:") {
+            if path.is_empty() {
+                self.error(field, "file path required");
+            }
+        } else if value == REDACTED {
+            self.error(field, "required");
+        } else {
+            self.required(field, value);
+        }
+    }
+
+    pub fn finish(self) -> Result<(), ValidationError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError(self.errors))
+        }
+    }
+}
+
+pub trait Validate {
+    fn check(&self, v: &mut Validator);
+
+    /// checks that only look at the config itself, references to other configs are not resolved
+    fn validate(&self) -> Result<(), ValidationError> {
+        let mut v = Validator::default();
+        self.check(&mut v);
+        v.finish()
+    }
+}
+
+impl Validate for DdnsConfig {
+    fn check(&self, v: &mut Validator) {
+        check_list(v, "ddns", &self.ddns, |c| &c.name);
+        check_list(v, "provider", &self.provider, |p| &p.name);
+        check_list(v, "webhook", &self.webhook, |w| &w.name);
+    }
+}
+
+fn check_list<T: Validate>(v: &mut Validator, field: &str, items: &[T], name: fn(&T) -> &str) {
+    let mut names = HashSet::new();
+    for (i, item) in items.iter().enumerate() {
+        v.scope(format!("{field}[{i}]"), |v| {
+            item.check(v);
+            if !names.insert(name(item)) {
+                v.error("name", format!("duplicate name: {}", name(item)));
+            }
+        });
+    }
+}
+
+impl Validate for DnsConfig {
+    fn check(&self, v: &mut Validator) {
+        v.required("name", &self.name);
+        self.domain.check(v);
+        if !(MIN_INTERVAL..=MAX_INTERVAL).contains(&self.interval) {
+            v.error(
+                "interval",
+                format!("must be {MIN_INTERVAL}-{MAX_INTERVAL} seconds"),
+            );
+        }
+        v.required("provider", &self.provider);
+        for (config, family) in [(&self.ipv4, Family::Ipv4), (&self.ipv6, Family::Ipv6)] {
+            // the form keeps the fields of a disabled family, they may be left empty
+            if let Some(config) = config.as_ref().filter(|config| config.enabled) {
+                v.scope(family.to_string(), |v| check_addr(v, config, family));
+            }
+        }
+        if let Some(schedule) = &self.schedule {
+            v.scope("schedule", |v| {
+                if let Some(Err(e)) = schedule.cron.as_deref().map(Cron::from_str) {
+                    v.error("cron", format!("{e:#}"));
+                }
+                if let Some(Err(e)) = schedule.quiet.as_deref().map(Quiet::from_str) {
+                    v.error("quiet", format!("{e:#}"));
+                }
+            });
+        }
+    }
+}
+
+impl Validate for Domain {
+    fn check(&self, v: &mut Validator) {
+        if !is_hostname(&self.domain) || !self.domain.contains('.') {
+            v.error("domain", format!("invalid domain: {}", self.domain));
+        }
+        // `@` is the domain itself, `*` a wildcard record
+        let subdomain = self.subdomain.strip_prefix("*.").unwrap_or(&self.subdomain);
+        if !matches!(subdomain, "@" | "*") && !is_hostname(subdomain) {
+            v.error(
+                "subdomain",
+                format!("invalid subdomain: {}", self.subdomain),
+            );
+        }
+    }
+}
+
+pub fn check_addr(v: &mut Validator, config: &AddrConfig, family: Family) {
+    match &config.method {
+        Method::Api { endpoint } => check_url(v, "endpoint", endpoint),
+        Method::Nic { interface } => v.required("interface", interface),
+        Method::Cmd { command } => v.required("command", command),
+        Method::Dns { server, query, .. } => {
+            v.required("server", server);
+            if !is_hostname(query) {
+                v.error("query", format!("invalid domain: {query}"));
+            }
+        }
+        Method::Upnp { location } if !location.is_empty() => check_url(v, "location", location),
+        Method::Upnp { .. } => {}
+        Method::NatPmp { gateway } => {
+            if !gateway.is_empty() && IpAddr::from_str(gateway).is_err() {
+                v.error("gateway", format!("invalid address: {gateway}"));
+            }
+        }
+    }
+    if let Some(suffix) = &config.suffix
+        && suffix.addr.is_ipv4() != (family == Family::Ipv4)
+    {
+        v.error("suffix", format!("{family} suffix required"));
+    }
+}
+
+fn check_url(v: &mut Validator, field: &str, value: &str) {
+    match url::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => v.error(field, format!("unsupported scheme: {}", url.scheme())),
+        Err(e) => v.error(field, format!("invalid url {value}: {e}")),
+    }
+}
+
+impl Validate for Provider {
+    fn check(&self, v: &mut Validator) {
+        v.required("name", &self.name);
+        self.config.check(v);
+    }
+}
+
+impl Validate for RealProvider {
+    fn check(&self, v: &mut Validator) {
+        match self {
+            Self::Tencent(config) => config.check(v),
+            Self::Cloudflare(config) => config.check(v),
+            Self::Aliyun(config) => config.check(v),
+        }
+    }
+}
+
+impl Validate for Webhook {
+    fn check(&self, v: &mut Validator) {
+        v.required("name", &self.name);
+        if let Err(e) = webhook::check_template(&self.value) {
+            v.error("value", format!("{e:#}"));
+        }
+    }
+}
+
+/// ascii labels of letters, digits, `-` and `_`, as accepted by the providers
+pub fn is_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(result: Result<(), ValidationError>) -> Vec<String> {
+        result
+            .err()
+            .map(|e| e.0.into_iter().map(|e| e.field).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn dns_config() {
+        let config = |patch: serde_json::Value| {
+            let mut config = json!({
+                "name": "home",
+                "domain": "example.com",
+                "subdomain": "www",
+                "interval": 300,
+                "ipv4": { "enabled": true, "method": "api", "endpoint": "https://4.ipw.cn" },
+                "ipv6": { "enabled": false, "method": "api", "endpoint": "" },
+                "provider": "cf",
+            });
+            for (key, value) in patch.as_object().unwrap() {
+                config[key] = value.clone();
+            }
+            serde_json::from_value::<DnsConfig>(config).unwrap()
+        };
+        assert!(config(json!({})).validate().is_ok());
+        assert!(config(json!({ "subdomain": "@" })).validate().is_ok());
+        assert!(config(json!({ "subdomain": "*.dev" })).validate().is_ok());
+
+        let invalid = config(json!({
+            "domain": "exa mple.com",
+            "subdomain": "-www",
+            "interval": 0,
+            "ipv4": { "enabled": true, "method": "cmd", "command": " " },
+            "ipv6": { "enabled": true, "method": "nic", "interface": "eth0", "suffix": "10.0.0.1/8" },
+            "schedule": { "cron": "* * *", "quiet": "23:00" },
+        }));
+        assert_eq!(
+            fields(invalid.validate()),
+            [
+                "domain",
+                "subdomain",
+                "interval",
+                "ipv4.command",
+                "ipv6.suffix",
+                "schedule.cron",
+                "schedule.quiet"
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_fields() {
+        let config: DdnsConfig = serde_json::from_value(json!({
+            "listen": "0.0.0.0:6789",
+            "provider": [
+                { "name": "cf", "kind": "cloudflare", "apiKey": "key" },
+                { "name": "cf", "kind": "tencent", "secretId": "", "secretKey": "key" },
+            ],
+            "webhook": [{ "name": "hook", "value": "POST https://example.com\nBad Header\n\n{}" }],
+        }))
+        .unwrap();
+        assert_eq!(
+            fields(config.validate()),
+            [
+                "provider[1].secretId",
+                "provider[1].name",
+                "webhook[0].value"
+            ]
+        );
+
+        let webhook = Webhook {
+            name: "hook".to_string(),
+            value: "POST https://example.com\nContent-Type: application/json\n\n{}".to_string(),
+        };
+        assert!(webhook.validate().is_ok());
+    }
+
+    #[test]
+    fn hostname() {
+        assert!(is_hostname("example.com"));
+        assert!(is_hostname("_acme-challenge.example.com."));
+        assert!(!is_hostname(""));
+        assert!(!is_hostname("a..b"));
+        assert!(!is_hostname("例子.com"));
+        assert!(!is_hostname(&format!("{}.com", "a".repeat(64))));
+    }
+}
@@ -1,5 +1,16 @@
-use crate::provider::{DnsAddr, Domain, aliyun::client::Aliyun};
+use crate::{
+    model::{
+        ErrorKind, Secret,
+        validate::{Validate, Validator},
+    },
+    provider::{
+        Change, DnsAddr, Domain, Record, Zone,
+        aliyun::client::{Aliyun, AliyunError},
+        error::matches,
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::error::Error;
 use tracing::info;
 
 mod client;
@@ -7,12 +18,33 @@ mod client;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-    secret_id: String,
-    secret_key: String,
+    secret_id: Secret,
+    secret_key: Secret,
+}
+
+impl Config {
+    pub fn secrets_mut(&mut self) -> Vec<&mut Secret> {
+        vec![&mut self.secret_id, &mut self.secret_key]
+    }
+
+    /// the plain secrets, read once per client rather than for every signature
+    async fn resolve(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            secret_id: Secret(self.secret_id.resolve().await?.into_owned()),
+            secret_key: Secret(self.secret_key.resolve().await?.into_owned()),
+        })
+    }
+}
+
+impl Validate for Config {
+    fn check(&self, v: &mut Validator) {
+        v.secret("secretId", &self.secret_id);
+        v.secret("secretKey", &self.secret_key);
+    }
 }
 
 pub async fn update(config: &Config, domain: Domain, addr: DnsAddr) -> anyhow::Result<()> {
-    let client = Aliyun::new(config);
+    let client = Aliyun::new(config).await?;
     let Some(record) = client
         .query_records(&domain, addr)
         .await?
@@ -28,3 +60,70 @@ pub async fn update(config: &Config, domain: Domain, addr: DnsAddr) -> anyhow::R
     }
     Ok(())
 }
+
+pub(super) async fn plan(
+    config: &Config,
+    domain: Domain,
+    addr: DnsAddr,
+) -> anyhow::Result<Vec<Change>> {
+    let client = Aliyun::new(config).await?;
+    let record = client
+        .query_records(&domain, addr)
+        .await?
+        .into_iter()
+        .next();
+    let change = match record {
+        None => Change::create(domain.fqdn(), addr),
+        Some(record) => Change::update(domain.fqdn(), addr.dns_type.into(), record.value, addr),
+    };
+    Ok(vec![change])
+}
+
+pub(super) async fn zones(config: &Config) -> anyhow::Result<Vec<Zone>> {
+    let domains = Aliyun::new(config).await?.list_domains().await?;
+    let zones = domains.into_iter().map(|domain| Zone {
+        id: domain.domain_id,
+        name: domain.domain_name,
+        status: None,
+    });
+    Ok(zones.collect())
+}
+
+pub(super) async fn records(config: &Config, zone: &str) -> anyhow::Result<Vec<Record>> {
+    let records = Aliyun::new(config).await?.list_records(zone).await?;
+    let records = records.into_iter().map(|record| Record {
+        id: record.record_id,
+        subdomain: record.rr,
+        kind: record.kind,
+        value: record.value,
+        ttl: record.ttl,
+    });
+    Ok(records.collect())
+}
+
+pub(super) fn classify(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    let code = &*error.downcast_ref::<AliyunError>()?.code;
+    let auth = [
+        "InvalidAccessKeyId",
+        "InvalidAccessKeySecret",
+        "SignatureDoesNotMatch",
+        "Forbidden",
+    ];
+    let kind = match code {
+        _ if matches(code, &auth) => ErrorKind::Auth,
+        _ if matches(code, &["Throttling"]) => ErrorKind::RateLimited,
+        _ if matches(
+            code,
+            &["ServiceUnavailable", "InternalError", "UnknownError"],
+        ) =>
+        {
+            ErrorKind::Transient
+        }
+        "InvalidDomainName.NoExist" | "IncorrectDomainUser" => ErrorKind::NoZone,
+        "DomainRecordNotBelongToUser" | "InvalidRecordId.NoExist" => ErrorKind::NotFound,
+        "DomainRecordDuplicate" | "DomainRecordConflict" => ErrorKind::Conflict,
+        _ if matches(code, &["Invalid", "MissingParameter"]) => ErrorKind::Invalid,
+        _ => ErrorKind::Unknown,
+    };
+    Some(kind)
+}
@@ -4,10 +4,11 @@ use crate::{
         aliyun::{
             Config,
             client::{
-                schema::{AliyunError, DnsRecord, RecordId},
+                schema::{DnsRecord, DomainInfo, RecordId},
                 signature::create_signature,
             },
         },
+        http_client,
     },
     treemap,
 };
@@ -24,23 +25,32 @@ mod signature;
 
 #[path = "schema.rs"]
 mod schema;
+
+pub(super) use schema::AliyunError;
+
 const VERSION: &str = "2015-01-09";
 
 const HOST: &str = "alidns.aliyuncs.com";
 
 const ENDPOINT: &str = "https://alidns.aliyuncs.com";
 
-pub(super) struct Aliyun<'a> {
-    config: &'a Config,
+/// the largest page of the domain list
+const PAGE_SIZE: usize = 100;
+/// the largest page of the record list
+const RECORD_PAGE_SIZE: usize = 500;
+
+pub(super) struct Aliyun {
+    /// with the secrets resolved
+    config: Config,
     client: Client,
 }
 
-impl<'a> Aliyun<'a> {
-    pub fn new(config: &'a Config) -> Self {
-        Self {
-            config,
-            client: Client::new(),
-        }
+impl Aliyun {
+    pub async fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            config: config.resolve().await?,
+            client: http_client().build()?,
+        })
     }
     pub async fn query_records(
         &self,
@@ -72,6 +82,75 @@ impl<'a> Aliyun<'a> {
         Ok(records)
     }
 
+    /// every record of the domain
+    pub async fn list_records(&self, domain: &str) -> anyhow::Result<Vec<DnsRecord>> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "DomainRecords")]
+            records: Records,
+        }
+        #[derive(Deserialize)]
+        struct Records {
+            #[serde(rename = "Record")]
+            record: Vec<DnsRecord>,
+        }
+        let mut records = vec![];
+        let page_size = RECORD_PAGE_SIZE.to_string();
+        for page in 1.. {
+            let page = page.to_string();
+            let query = treemap! {
+                "DomainName" => domain,
+                "PageNumber" => &*page,
+                "PageSize" => &*page_size,
+            };
+            let list = self
+                .send::<Response>("DescribeDomainRecords", query)
+                .await?
+                .records
+                .record;
+            let last = list.len() < RECORD_PAGE_SIZE;
+            records.extend(list);
+            if last {
+                break;
+            }
+        }
+        Ok(records)
+    }
+
+    /// every domain of the account
+    pub async fn list_domains(&self) -> anyhow::Result<Vec<DomainInfo>> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "Domains")]
+            domains: Domains,
+        }
+        #[derive(Deserialize)]
+        struct Domains {
+            #[serde(rename = "Domain")]
+            domain: Vec<DomainInfo>,
+        }
+        let mut domains = vec![];
+        let page_size = PAGE_SIZE.to_string();
+        for page in 1.. {
+            let page = page.to_string();
+            let query = treemap! {
+                "PageNumber" => &*page,
+                "PageSize" => &*page_size,
+            };
+            let list = self
+                .send::<Response>("DescribeDomains", query)
+                .await?
+                .domains
+                .domain;
+            let last = list.len() < PAGE_SIZE;
+            domains.extend(list);
+            if last {
+                break;
+            }
+        }
+        Ok(domains)
+    }
+
     pub async fn update_record(&self, addr: DnsAddr, record: &DnsRecord) -> anyhow::Result<String> {
         let value = addr.to_string();
         let query = treemap! {
@@ -156,15 +235,16 @@ fn customize_url_encode(query: BTreeMap<&str, &str>) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::Secret;
     use std::net::{IpAddr, Ipv4Addr};
 
     #[tokio::test]
     async fn test() {
         let config = Config {
-            secret_id: dotenvy::var("ALIYUN_SECRET_ID").unwrap(),
-            secret_key: dotenvy::var("ALIYUN_SECRET_KEY").unwrap(),
+            secret_id: Secret(dotenvy::var("ALIYUN_SECRET_ID").unwrap()),
+            secret_key: Secret(dotenvy::var("ALIYUN_SECRET_KEY").unwrap()),
         };
-        let aliyun = Aliyun::new(&config);
+        let aliyun = Aliyun::new(&config).await.unwrap();
         let e = aliyun
             .query_records(
                 &Domain {
@@ -30,6 +30,17 @@ pub struct DnsRecord {
     pub value: String,
     pub record_id: String,
     pub rr: String,
+    #[serde(rename = "Type", default)]
+    pub kind: String,
+    #[serde(rename = "TTL", default)]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DomainInfo {
+    pub domain_id: String,
+    pub domain_name: String,
 }
 
 #[derive(Deserialize)]
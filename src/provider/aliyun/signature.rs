@@ -45,11 +45,12 @@ pub(super) fn create_signature(
     .join("\n");
 
     let req = format!("ACS3-HMAC-SHA256\n{}", &hex_sha256(canonical_request));
-    let signed = hex::encode(hmac_sha256(&config.secret_key, &req)?);
+    // the client resolved the secrets
+    let signed = hex::encode(hmac_sha256(config.secret_key.0.as_bytes(), &req)?);
 
     let authorization = format!(
         "ACS3-HMAC-SHA256 Credential={},SignedHeaders={},Signature={}",
-        &config.secret_id, &signed_header_names, &signed
+        config.secret_id.0, &signed_header_names, &signed
     );
     Ok(authorization)
 }
@@ -57,6 +58,7 @@ pub(super) fn create_signature(
 #[cfg(test)]
 mod tests {
     use super::{super::Config, create_signature};
+    use crate::model::Secret;
     use time::{UtcDateTime, format_description::well_known::Rfc3339};
 
     #[test]
@@ -64,8 +66,8 @@ mod tests {
         let date = UtcDateTime::from_unix_timestamp(1698315752).unwrap();
         let signature = create_signature(
             &Config {
-                secret_id: "YourAccessKeyId".to_string(),
-                secret_key: "YourAccessKeySecret".to_string(),
+                secret_id: Secret("YourAccessKeyId".to_string()),
+                secret_key: Secret("YourAccessKeySecret".to_string()),
             },
             &date.format(&Rfc3339).unwrap(),
             "RunInstances",
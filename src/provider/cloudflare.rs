@@ -1,25 +1,115 @@
-use crate::provider::{DnsAddr, Domain, cloudflare::client::Cloudflare};
+use crate::{
+    model::{
+        ErrorKind, Secret,
+        validate::{Validate, Validator},
+    },
+    provider::{
+        Change, DnsAddr, Domain, ProviderError, Record,
+        cloudflare::client::{ApiError, Cloudflare, Zone},
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::error::Error;
 
 mod client;
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-    api_key: String,
+    api_key: Secret,
+}
+
+impl Config {
+    pub fn secrets_mut(&mut self) -> Vec<&mut Secret> {
+        vec![&mut self.api_key]
+    }
+}
+
+impl Validate for Config {
+    fn check(&self, v: &mut Validator) {
+        v.secret("apiKey", &self.api_key);
+    }
 }
 
 pub(super) async fn update(config: &Config, domain: Domain, addr: DnsAddr) -> anyhow::Result<()> {
-    let client = Cloudflare::new(config)?;
-    let zone = client
-        .query_zone(&domain)
-        .await?
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("cannot find zone for {}", domain.domain))?;
+    let client = Cloudflare::new(config).await?;
+    let zone = find_zone(&client, &domain).await?;
     let records = client.query_records(&zone).await?;
     if records.is_empty() {
         return client.create_records(&domain, addr, &zone).await;
     }
     client.update_records(addr, &zone, records).await
 }
+
+pub(super) async fn plan(
+    config: &Config,
+    domain: Domain,
+    addr: DnsAddr,
+) -> anyhow::Result<Vec<Change>> {
+    let client = Cloudflare::new(config).await?;
+    let zone = find_zone(&client, &domain).await?;
+    let records = client.query_records(&zone).await?;
+    if records.is_empty() {
+        return Ok(vec![Change::create(domain.domain, addr)]);
+    }
+    let changes = records
+        .into_iter()
+        .map(|record| Change::update(record.name, record.kind, record.content, addr))
+        .collect();
+    Ok(changes)
+}
+
+pub(super) async fn zones(config: &Config) -> anyhow::Result<Vec<super::Zone>> {
+    let client = Cloudflare::new(config).await?;
+    // an expired or disabled token is told apart from one missing permissions
+    let status = client.verify_token().await?;
+    if status != "active" {
+        return Err(ProviderError::Auth(format!("the token is {status}")).into());
+    }
+    let zones = client.list_zones().await?;
+    let zones = zones.into_iter().map(|zone| super::Zone {
+        id: zone.id,
+        name: zone.name,
+        status: if zone.paused {
+            Some("paused".to_string())
+        } else {
+            zone.status
+        },
+    });
+    Ok(zones.collect())
+}
+
+pub(super) async fn records(config: &Config, zone: &str) -> anyhow::Result<Vec<Record>> {
+    let client = Cloudflare::new(config).await?;
+    let zone = find_zone(&client, &Domain::zone(zone)).await?;
+    let records = client.list_records(&zone).await?;
+    let records = records.into_iter().map(|record| Record {
+        id: record.id,
+        subdomain: super::subdomain(&record.name, &zone.name),
+        kind: record.kind,
+        value: record.content,
+        ttl: record.ttl,
+    });
+    Ok(records.collect())
+}
+
+async fn find_zone(client: &Cloudflare, domain: &Domain) -> anyhow::Result<Zone> {
+    let zone = client.query_zone(domain).await?.into_iter().next();
+    Ok(zone.ok_or_else(|| ProviderError::NoZone(domain.domain.clone()))?)
+}
+
+pub(super) fn classify(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    let kind = match error.downcast_ref::<ApiError>()?.code {
+        // invalid token, authentication error, unknown key, invalid headers
+        9109 | 10000 | 9103 | 6003 => ErrorKind::Auth,
+        // "Please wait and consider throttling your request speed"
+        971 => ErrorKind::RateLimited,
+        // record not found, no route / invalid identifier
+        81044 | 7000 | 7003 => ErrorKind::NotFound,
+        // record already exists
+        81053 | 81057 | 81058 => ErrorKind::Conflict,
+        1000..2000 | 9000..10000 => ErrorKind::Invalid,
+        _ => ErrorKind::Unknown,
+    };
+    Some(kind)
+}
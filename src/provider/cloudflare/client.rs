@@ -1,13 +1,19 @@
-use crate::provider::{DnsAddr, Domain, cloudflare::Config};
+use crate::provider::{DnsAddr, Domain, ProviderError, cloudflare::Config, http_client};
 use anyhow::anyhow;
 use reqwest::{
-    Client, ClientBuilder,
-    header::{AUTHORIZATION, HeaderMap, HeaderValue},
+    Client, RequestBuilder, StatusCode,
+    header::{AUTHORIZATION, HeaderMap, HeaderValue, RETRY_AFTER},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    time::Duration,
 };
-use serde::{Deserialize, Serialize};
-use std::fmt::{Debug, Display, Formatter};
 
 const ZONE_ENDPOINT: &str = "https://api.cloudflare.com/client/v4/zones";
+const VERIFY_ENDPOINT: &str = "https://api.cloudflare.com/client/v4/user/tokens/verify";
+/// the largest page of the zone list, records are listed in pages of the same size
+const PAGE_SIZE: usize = 50;
 const DNS_ENDPOINT: &str = "https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records";
 const UPDATE_DNS_ENDPOINT: &str =
     "https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records/{dns_record_id}";
@@ -16,35 +22,77 @@ pub struct Cloudflare {
     client: Client,
 }
 impl Cloudflare {
-    pub fn new(config: &Config) -> anyhow::Result<Self> {
+    pub async fn new(config: &Config) -> anyhow::Result<Self> {
         let mut headers = HeaderMap::new();
-        let auth_value = format!("Bearer {}", &config.api_key);
+        let auth_value = format!("Bearer {}", config.api_key.resolve().await?);
         let header = HeaderValue::from_bytes(auth_value.as_bytes())?;
         headers.insert(AUTHORIZATION, header);
-        let client = ClientBuilder::new().default_headers(headers).build()?;
+        let client = http_client().default_headers(headers).build()?;
         Ok(Self { client })
     }
     pub async fn query_zone(&self, domain: &Domain) -> anyhow::Result<Vec<Zone>> {
-        self.client
-            .get(ZONE_ENDPOINT)
-            .query(&[("name", &*domain.domain), ("page", "1"), ("per_page", "50")])
-            .send()
-            .await?
-            .json::<ApiResponse<Vec<Zone>>>()
-            .await?
-            .into()
+        let request = self.client.get(ZONE_ENDPOINT).query(&[
+            ("name", &*domain.domain),
+            ("page", "1"),
+            ("per_page", "50"),
+        ]);
+        send(request).await
+    }
+
+    /// the status of the token, `active` if it can be used
+    pub async fn verify_token(&self) -> anyhow::Result<String> {
+        #[derive(Deserialize, Default)]
+        struct Token {
+            status: String,
+        }
+        let token: Token = send(self.client.get(VERIFY_ENDPOINT)).await?;
+        Ok(token.status)
+    }
+
+    /// every zone the token can read
+    pub async fn list_zones(&self) -> anyhow::Result<Vec<Zone>> {
+        let mut zones = vec![];
+        for page in 1.. {
+            let request = self
+                .client
+                .get(ZONE_ENDPOINT)
+                .query(&[("page", page), ("per_page", PAGE_SIZE)]);
+            let list: Vec<Zone> = send(request).await?;
+            let last = list.len() < PAGE_SIZE;
+            zones.extend(list);
+            if last {
+                break;
+            }
+        }
+        Ok(zones)
     }
 
     pub async fn query_records(&self, zone: &Zone) -> anyhow::Result<Vec<DnsRecord>> {
         let url = DNS_ENDPOINT.replace("{zone_id}", &zone.id);
-        self.client
-            .get(url)
-            .query(&[("name", &*zone.name), ("page", "1"), ("per_page", "50")])
-            .send()
-            .await?
-            .json::<ApiResponse<Vec<DnsRecord>>>()
-            .await?
-            .into()
+        let request =
+            self.client
+                .get(url)
+                .query(&[("name", &*zone.name), ("page", "1"), ("per_page", "50")]);
+        send(request).await
+    }
+
+    /// every record of the zone
+    pub async fn list_records(&self, zone: &Zone) -> anyhow::Result<Vec<DnsRecord>> {
+        let url = DNS_ENDPOINT.replace("{zone_id}", &zone.id);
+        let mut records = vec![];
+        for page in 1.. {
+            let request = self
+                .client
+                .get(&url)
+                .query(&[("page", page), ("per_page", PAGE_SIZE)]);
+            let list: Vec<DnsRecord> = send(request).await?;
+            let last = list.len() < PAGE_SIZE;
+            records.extend(list);
+            if last {
+                break;
+            }
+        }
+        Ok(records)
     }
 
     pub async fn update_records(
@@ -63,20 +111,13 @@ impl Cloudflare {
             let url = UPDATE_DNS_ENDPOINT
                 .replace("{zone_id}", &zone.id)
                 .replace("{dns_record_id}", &record.id);
-            let response = self
-                .client
-                .patch(url)
-                .json(&ModifyingDnsRecord {
-                    name: &*name,
-                    kind,
-                    proxied,
-                    content: addr.to_string(),
-                })
-                .send()
-                .await?
-                .json::<ApiResponse<()>>()
-                .await?;
-            Into::<anyhow::Result<()>>::into(response)?;
+            let request = self.client.patch(url).json(&ModifyingDnsRecord {
+                name: &*name,
+                kind,
+                proxied,
+                content: addr.to_string(),
+            });
+            send::<()>(request).await?;
         }
         Ok(())
     }
@@ -88,23 +129,41 @@ impl Cloudflare {
         zone: &Zone,
     ) -> anyhow::Result<()> {
         let url = DNS_ENDPOINT.replace("{zone_id}", &zone.id);
-        let resp = self
-            .client
-            .post(url)
-            .json(&ModifyingDnsRecord {
-                name: &domain.domain,
-                content: addr.to_string(),
-                kind: addr.dns_type.to_string(),
-                proxied: false,
-            })
-            .send()
-            .await?
-            .json::<ApiResponse<()>>()
-            .await?;
-        Into::<anyhow::Result<()>>::into(resp)
+        let request = self.client.post(url).json(&ModifyingDnsRecord {
+            name: &domain.domain,
+            content: addr.to_string(),
+            kind: addr.dns_type.to_string(),
+            proxied: false,
+        });
+        send(request).await
     }
 }
 
+async fn send<T: DeserializeOwned + Default>(request: RequestBuilder) -> anyhow::Result<T> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map(Duration::from_secs);
+        let message = format!("cloudflare api returned {status}");
+        return Err(ProviderError::RateLimited {
+            retry_after,
+            message,
+        }
+        .into());
+    }
+    // outages are reported by status, the body may not even be json
+    if status.is_server_error() {
+        if let Err(e) = response.error_for_status_ref() {
+            return Err(e.into());
+        }
+    }
+    response.json::<ApiResponse<T>>().await?.into()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ApiResponse<T> {
     #[serde(default)]
@@ -121,16 +180,16 @@ impl<T> Into<anyhow::Result<T>> for ApiResponse<T> {
                 .result
                 .ok_or_else(|| anyhow!("Api returned success. but result is none"));
         }
-        if let Some(e) = &self.errors.first() {
-            anyhow::bail!("Failed to call cloudflare api. code: {e:#}");
+        if let Some(e) = self.errors.into_iter().next() {
+            return Err(anyhow::Error::new(e).context("Failed to call cloudflare api"));
         };
         anyhow::bail!("Unknown error to request cloudflare api");
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ApiError {
-    pub code: u16,
+pub(in crate::provider) struct ApiError {
+    pub code: u32,
     pub message: String,
     #[serde(rename = "error_chain", default)]
     pub errors: Vec<ApiError>,
@@ -153,6 +212,8 @@ pub struct Zone {
     pub id: String,
     pub name: String,
     pub paused: bool,
+    #[serde(default)]
+    pub status: Option<String>,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DnsRecord {
@@ -163,6 +224,8 @@ pub struct DnsRecord {
     pub content: String,
     pub proxied: bool,
     pub proxiable: bool,
+    #[serde(default)]
+    pub ttl: Option<u32>,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModifyingDnsRecord<'a> {
@@ -0,0 +1,141 @@
+use crate::{
+    model::ErrorKind,
+    provider::{aliyun, cloudflare, tencent},
+};
+use std::{error::Error, io, time::Duration};
+use thiserror::Error;
+use tokio::time::error::Elapsed;
+
+#[derive(Error, Debug, Clone)]
+pub enum ProviderError {
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    #[error("record not found: {0}")]
+    NotFound(String),
+
+    #[error("zone not found: {0}")]
+    NoZone(String),
+
+    #[error("rate limited: {message}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    #[error("transient error: {0}")]
+    Transient(String),
+
+    #[error("invalid request: {0}")]
+    Invalid(String),
+
+    #[error("{0}")]
+    Unknown(String),
+}
+
+impl ProviderError {
+    fn new(kind: ErrorKind, message: String) -> Self {
+        match kind {
+            ErrorKind::Auth => Self::Auth(message),
+            ErrorKind::NotFound => Self::NotFound(message),
+            ErrorKind::NoZone => Self::NoZone(message),
+            ErrorKind::RateLimited => Self::RateLimited {
+                retry_after: None,
+                message,
+            },
+            ErrorKind::Conflict => Self::Conflict(message),
+            ErrorKind::Transient => Self::Transient(message),
+            ErrorKind::Invalid => Self::Invalid(message),
+            ErrorKind::Detect | ErrorKind::Unknown => Self::Unknown(message),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Auth(_) => ErrorKind::Auth,
+            Self::NotFound(_) => ErrorKind::NotFound,
+            Self::NoZone(_) => ErrorKind::NoZone,
+            Self::RateLimited { .. } => ErrorKind::RateLimited,
+            Self::Conflict(_) => ErrorKind::Conflict,
+            Self::Transient(_) => ErrorKind::Transient,
+            Self::Invalid(_) => ErrorKind::Invalid,
+            Self::Unknown(_) => ErrorKind::Unknown,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Transient(_) | Self::RateLimited { .. })
+    }
+}
+
+impl From<anyhow::Error> for ProviderError {
+    fn from(error: anyhow::Error) -> Self {
+        if let Some(e) = error.downcast_ref::<ProviderError>() {
+            return e.clone();
+        }
+        let message = format!("{error:#}");
+        match error.chain().find_map(classify) {
+            Some(kind) => Self::new(kind, message),
+            None => Self::Unknown(message),
+        }
+    }
+}
+
+fn classify(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    if let Some(e) = error.downcast_ref::<ProviderError>() {
+        return Some(e.kind());
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() || e.is_connect() {
+            return Some(ErrorKind::Transient);
+        }
+        return e.status().map(|status| match status.as_u16() {
+            401 | 403 => ErrorKind::Auth,
+            404 => ErrorKind::NotFound,
+            409 => ErrorKind::Conflict,
+            429 => ErrorKind::RateLimited,
+            500.. => ErrorKind::Transient,
+            _ => ErrorKind::Invalid,
+        });
+    }
+    if error.is::<io::Error>() || error.is::<Elapsed>() {
+        return Some(ErrorKind::Transient);
+    }
+    tencent::classify(error)
+        .or_else(|| aliyun::classify(error))
+        .or_else(|| cloudflare::classify(error))
+}
+
+/// whether `code` starts with one of `prefixes`, error codes are usually `Category.Detail`
+pub(super) fn matches(code: &str, prefixes: &[&str]) -> bool {
+    prefixes.iter().any(|prefix| code.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn classify_chain() {
+        let e = Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionReset))
+            .context("query records")
+            .unwrap_err();
+        let e = ProviderError::from(e);
+        assert_eq!(e.kind(), ErrorKind::Transient);
+        assert_eq!(
+            e.to_string(),
+            "transient error: query records: connection reset"
+        );
+
+        let e = ProviderError::from(anyhow::Error::from(ProviderError::NoZone("a.com".into())));
+        assert_eq!(e.kind(), ErrorKind::NoZone);
+
+        let e = ProviderError::from(anyhow::anyhow!("unexpected response"));
+        assert_eq!(e.kind(), ErrorKind::Unknown);
+        assert!(!e.is_retryable());
+    }
+}
@@ -1,14 +1,18 @@
-use crate::model::{Provider, RealProvider};
+use crate::model::{ErrorKind, Provider, RealProvider};
+use crate::service::retry::http_client;
+use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, net::IpAddr, ops::Deref};
 use url::form_urlencoded::parse;
 
 use crate::model;
 pub use aliyun::Config as AliyunConfig;
 pub use cloudflare::Config as CloudflareConfig;
+pub use error::ProviderError;
 pub use tencent::Config as TencentConfig;
 mod aliyun;
 mod cloudflare;
 mod digest;
+mod error;
 mod tencent;
 
 #[macro_export]
@@ -49,6 +53,33 @@ impl From<&model::Domain> for Domain {
     }
 }
 
+impl Domain {
+    fn zone(zone: &str) -> Self {
+        Self {
+            domain: zone.into(),
+            subdomain: "@".into(),
+            params: Map::default(),
+        }
+    }
+
+    /// the full name of the record, `@` is the domain itself
+    fn fqdn(&self) -> String {
+        match &*self.subdomain {
+            "@" => self.domain.clone(),
+            subdomain => format!("{subdomain}.{}", self.domain),
+        }
+    }
+}
+
+/// the subdomain of a full record name in `zone`
+fn subdomain(name: &str, zone: &str) -> String {
+    match name.strip_suffix(zone).map(|rest| rest.strip_suffix('.')) {
+        Some(None) if name.len() == zone.len() => "@".into(),
+        Some(Some(subdomain)) => subdomain.into(),
+        _ => name.into(),
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub(self) struct DnsAddr {
     addr: IpAddr,
@@ -75,11 +106,178 @@ pub async fn update_ddns_record(
     domain: &model::Domain,
     provider: &Provider,
     addr: IpAddr,
-) -> anyhow::Result<()> {
+) -> Result<(), ProviderError> {
     use RealProvider::*;
-    match &provider.config {
+    let result = match &provider.config {
         Tencent(config) => tencent::update(config, domain.into(), addr.into()).await,
         Cloudflare(config) => cloudflare::update(config, domain.into(), addr.into()).await,
         Aliyun(config) => aliyun::update(config, domain.into(), addr.into()).await,
+    };
+    result.map_err(ProviderError::from)
+}
+
+/// a zone (domain) the credentials can manage
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Zone {
+    pub id: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+/// a record of a zone
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Record {
+    pub id: String,
+    /// `@` is the zone itself
+    pub subdomain: String,
+    pub kind: String,
+    pub value: String,
+    pub ttl: Option<u32>,
+}
+
+/// every zone the credentials can manage
+pub async fn list_zones(provider: &Provider) -> Result<Vec<Zone>, ProviderError> {
+    use RealProvider::*;
+    let result = match &provider.config {
+        Tencent(config) => tencent::zones(config).await,
+        Cloudflare(config) => cloudflare::zones(config).await,
+        Aliyun(config) => aliyun::zones(config).await,
+    };
+    result.map_err(ProviderError::from)
+}
+
+/// every record of `zone`
+pub async fn list_records(provider: &Provider, zone: &str) -> Result<Vec<Record>, ProviderError> {
+    use RealProvider::*;
+    let result = match &provider.config {
+        Tencent(config) => tencent::records(config, zone).await,
+        Cloudflare(config) => cloudflare::records(config, zone).await,
+        Aliyun(config) => aliyun::records(config, zone).await,
+    };
+    result.map_err(ProviderError::from)
+}
+
+/// what the provider answered for the credentials, see [`check`]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckResult {
+    pub ok: bool,
+    pub zones: Vec<Zone>,
+    pub error: Option<ErrorKind>,
+    /// why the check failed, or a warning
+    pub message: Option<String>,
+}
+
+/// authenticates and lists the zones, a failure is reported rather than returned
+pub async fn check(provider: &Provider) -> CheckResult {
+    match list_zones(provider).await {
+        Ok(zones) => CheckResult {
+            ok: true,
+            message: zones.is_empty().then(|| {
+                "no zone is visible, the credentials may lack the permission to read zones".into()
+            }),
+            zones,
+            error: None,
+        },
+        Err(e) => CheckResult {
+            ok: false,
+            zones: vec![],
+            error: Some(e.kind()),
+            message: Some(match e.kind() {
+                ErrorKind::Auth => format!("{e}, check the keys and their permissions"),
+                _ => e.to_string(),
+            }),
+        },
+    }
+}
+
+/// a change [`update_ddns_record`] would make to a record
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Change {
+    Create {
+        name: String,
+        kind: String,
+        value: String,
+    },
+    Update {
+        name: String,
+        kind: String,
+        from: String,
+        to: String,
+    },
+    Unchanged {
+        name: String,
+        kind: String,
+        value: String,
+    },
+}
+
+impl Change {
+    fn create(name: String, addr: DnsAddr) -> Self {
+        Self::Create {
+            name,
+            kind: addr.dns_type.to_string(),
+            value: addr.to_string(),
+        }
+    }
+
+    /// the change of an existing record with `value`
+    fn update(name: String, kind: String, value: String, addr: DnsAddr) -> Self {
+        let to = addr.to_string();
+        if value == to {
+            Self::Unchanged { name, kind, value }
+        } else {
+            Self::Update {
+                name,
+                kind,
+                from: value,
+                to,
+            }
+        }
+    }
+}
+
+/// the changes [`update_ddns_record`] would make, nothing is written
+pub async fn plan_ddns_record(
+    domain: &model::Domain,
+    provider: &Provider,
+    addr: IpAddr,
+) -> Result<Vec<Change>, ProviderError> {
+    use RealProvider::*;
+    let result = match &provider.config {
+        Tencent(config) => tencent::plan(config, domain.into(), addr.into()).await,
+        Cloudflare(config) => cloudflare::plan(config, domain.into(), addr.into()).await,
+        Aliyun(config) => aliyun::plan(config, domain.into(), addr.into()).await,
+    };
+    result.map_err(ProviderError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change() {
+        let addr = DnsAddr::from(IpAddr::from([1, 2, 3, 4]));
+        let domain = Domain::from(&model::Domain {
+            domain: "example.com".into(),
+            subdomain: "@".into(),
+        });
+        assert_eq!(domain.fqdn(), "example.com");
+        assert_eq!(subdomain("example.com", "example.com"), "@");
+        assert_eq!(subdomain("www.example.com", "example.com"), "www");
+        assert_eq!(subdomain("www.other.com", "example.com"), "www.other.com");
+        assert_eq!(
+            serde_json::to_value(Change::create(domain.fqdn(), addr)).unwrap(),
+            serde_json::json!({
+                "action": "create", "name": "example.com", "kind": "A", "value": "1.2.3.4"
+            })
+        );
+        let change = |value: &str| Change::update("a".into(), "A".into(), value.into(), addr);
+        assert!(matches!(change("1.2.3.4"), Change::Unchanged { .. }));
+        assert!(matches!(change("1.1.1.1"), Change::Update { from, .. } if from == "1.1.1.1"));
     }
 }
@@ -1,5 +1,16 @@
-use crate::provider::{DnsAddr, Domain, tencent::client::Tencent};
+use crate::{
+    model::{
+        ErrorKind, Secret,
+        validate::{Validate, Validator},
+    },
+    provider::{
+        Change, DnsAddr, Domain, Record, Zone,
+        error::matches,
+        tencent::client::{Tencent, TencentError},
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::error::Error;
 use tracing::info;
 
 mod client;
@@ -7,12 +18,33 @@ mod client;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-    secret_id: String,
-    secret_key: String,
+    secret_id: Secret,
+    secret_key: Secret,
+}
+
+impl Config {
+    pub fn secrets_mut(&mut self) -> Vec<&mut Secret> {
+        vec![&mut self.secret_id, &mut self.secret_key]
+    }
+
+    /// the plain secrets, read once per client rather than for every signature
+    async fn resolve(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            secret_id: Secret(self.secret_id.resolve().await?.into_owned()),
+            secret_key: Secret(self.secret_key.resolve().await?.into_owned()),
+        })
+    }
+}
+
+impl Validate for Config {
+    fn check(&self, v: &mut Validator) {
+        v.secret("secretId", &self.secret_id);
+        v.secret("secretKey", &self.secret_key);
+    }
 }
 
 pub(super) async fn update(config: &Config, domain: Domain, addr: DnsAddr) -> anyhow::Result<()> {
-    let tencent = Tencent::new(config);
+    let tencent = Tencent::new(config).await?;
     //TODO Duplicated
     let Some(record) = tencent
         .query_records(&domain, addr)
@@ -29,3 +61,66 @@ pub(super) async fn update(config: &Config, domain: Domain, addr: DnsAddr) -> an
     }
     Ok(())
 }
+
+pub(super) async fn plan(
+    config: &Config,
+    domain: Domain,
+    addr: DnsAddr,
+) -> anyhow::Result<Vec<Change>> {
+    let tencent = Tencent::new(config).await?;
+    let record = tencent
+        .query_records(&domain, addr)
+        .await?
+        .into_iter()
+        .next();
+    let change = match record {
+        None => Change::create(domain.fqdn(), addr),
+        Some(record) => Change::update(domain.fqdn(), addr.dns_type.into(), record.value, addr),
+    };
+    Ok(vec![change])
+}
+
+pub(super) async fn zones(config: &Config) -> anyhow::Result<Vec<Zone>> {
+    let domains = Tencent::new(config).await?.list_domains().await?;
+    let zones = domains.into_iter().map(|domain| Zone {
+        id: domain.id.to_string(),
+        name: domain.name,
+        status: Some(domain.status.to_lowercase()),
+    });
+    Ok(zones.collect())
+}
+
+pub(super) async fn records(config: &Config, zone: &str) -> anyhow::Result<Vec<Record>> {
+    let records = Tencent::new(config).await?.list_records(zone).await?;
+    let records = records.into_iter().map(|record| Record {
+        id: record.id.to_string(),
+        subdomain: record.name,
+        kind: record.kind,
+        value: record.value,
+        ttl: record.ttl,
+    });
+    Ok(records.collect())
+}
+
+pub(super) fn classify(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    let code = &*error.downcast_ref::<TencentError>()?.error.code;
+    let kind = match code {
+        _ if matches(code, &["AuthFailure", "UnauthorizedOperation"]) => ErrorKind::Auth,
+        _ if matches(code, &["RequestLimitExceeded"]) => ErrorKind::RateLimited,
+        _ if matches(code, &["InternalError", "ResourceUnavailable"]) => ErrorKind::Transient,
+        "InvalidParameterValue.DomainNotExists" | "ResourceNotFound.NoDataOfDomain" => {
+            ErrorKind::NoZone
+        }
+        _ if matches(code, &["ResourceNotFound"]) => ErrorKind::NotFound,
+        "InvalidParameter.DomainRecordExist" => ErrorKind::Conflict,
+        _ if matches(
+            code,
+            &["InvalidParameter", "MissingParameter", "LimitExceeded"],
+        ) =>
+        {
+            ErrorKind::Invalid
+        }
+        _ => ErrorKind::Unknown,
+    };
+    Some(kind)
+}
@@ -1,5 +1,5 @@
 use super::{Config, DnsAddr};
-use crate::provider::Domain;
+use crate::provider::{Domain, http_client};
 use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
 use reqwest::{Client, header};
 use schema::*;
@@ -11,22 +11,28 @@ mod schema;
 #[path = "signature.rs"]
 mod signature;
 
+pub(super) use schema::TencentError;
+
 const HOST: &str = "dnspod.tencentcloudapi.com";
 
 const ENDPOINT: &str = "https://dnspod.tencentcloudapi.com";
 
 const VERSION: &str = "2021-03-23";
 
-pub struct Tencent<'a> {
-    config: &'a Config,
+/// the largest page of the domain and record lists
+const PAGE_SIZE: u32 = 3000;
+
+pub struct Tencent {
+    /// with the secrets resolved
+    config: Config,
     client: Client,
 }
-impl<'a> Tencent<'a> {
-    pub fn new(config: &'a Config) -> Self {
-        Self {
-            config,
-            client: Client::new(),
-        }
+impl Tencent {
+    pub async fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            config: config.resolve().await?,
+            client: http_client().build()?,
+        })
     }
     pub async fn query_records(
         &self,
@@ -52,6 +58,61 @@ impl<'a> Tencent<'a> {
         Ok(record)
     }
 
+    /// every record of the domain
+    pub async fn list_records(&self, domain: &str) -> anyhow::Result<Vec<Record>> {
+        #[derive(Deserialize)]
+        pub struct Records {
+            #[serde(rename = "RecordList")]
+            records: Vec<Record>,
+        }
+        let mut records = vec![];
+        loop {
+            let data = DescribeAllRecords {
+                domain,
+                offset: records.len() as u32,
+                limit: PAGE_SIZE,
+            };
+            let result = self.send::<Records>("DescribeRecordList", &data).await;
+            let list = match result.map_err(|e| e.downcast::<TencentError>()) {
+                Ok(r) => r.records,
+                Err(Ok(e)) if e.error.code == "ResourceNotFound.NoDataOfRecord" => vec![],
+                Err(e) => return Err(e?.into()),
+            };
+            let last = list.len() < PAGE_SIZE as usize;
+            records.extend(list);
+            if last {
+                return Ok(records);
+            }
+        }
+    }
+
+    /// every domain of the account
+    pub async fn list_domains(&self) -> anyhow::Result<Vec<DomainInfo>> {
+        #[derive(Deserialize)]
+        pub struct Domains {
+            #[serde(rename = "DomainList")]
+            domains: Vec<DomainInfo>,
+        }
+        let mut domains = vec![];
+        loop {
+            let data = DescribeDomainList {
+                offset: domains.len() as u32,
+                limit: PAGE_SIZE,
+            };
+            let result = self.send::<Domains>("DescribeDomainList", &data).await;
+            let list = match result.map_err(|e| e.downcast::<TencentError>()) {
+                Ok(r) => r.domains,
+                Err(Ok(e)) if e.error.code == "ResourceNotFound.NoDataOfDomain" => vec![],
+                Err(e) => return Err(e?.into()),
+            };
+            let last = list.len() < PAGE_SIZE as usize;
+            domains.extend(list);
+            if last {
+                return Ok(domains);
+            }
+        }
+    }
+
     pub async fn update_record(
         &self,
         domain: &Domain,
@@ -112,20 +173,21 @@ impl<'a> Tencent<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::model::Secret;
     use serde_json::Value;
 
     #[tokio::test]
     async fn test_send() {
         let config = Config {
-            secret_id: dotenvy::var("TENCENT_SECRET_ID").unwrap(),
-            secret_key: dotenvy::var("TENCENT_SECRET_KEY").unwrap(),
+            secret_id: Secret(dotenvy::var("TENCENT_SECRET_ID").unwrap()),
+            secret_key: Secret(dotenvy::var("TENCENT_SECRET_KEY").unwrap()),
         };
         let api = DescribeRecordList {
             domain: "zhouxi.me",
             record_type: "A",
             subdomain: "@",
         };
-        let tencent = Tencent::new(&config);
+        let tencent = Tencent::new(&config).await.unwrap();
         assert!(
             tencent
                 .send::<Value>("DescribeRecordList", &api)
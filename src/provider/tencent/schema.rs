@@ -69,6 +69,12 @@ pub struct Record {
     pub value: String,
     #[serde(rename = "Line")]
     pub line: String,
+    #[serde(rename = "Name", default)]
+    pub name: String,
+    #[serde(rename = "Type", default)]
+    pub kind: String,
+    #[serde(rename = "TTL", default)]
+    pub ttl: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
@@ -93,6 +99,31 @@ pub struct DescribeRecordList<'a> {
     pub record_type: &'a str,
 }
 #[derive(Serialize)]
+pub struct DescribeAllRecords<'a> {
+    #[serde(rename = "Domain")]
+    pub domain: &'a str,
+    #[serde(rename = "Offset")]
+    pub offset: u32,
+    #[serde(rename = "Limit")]
+    pub limit: u32,
+}
+#[derive(Serialize)]
+pub struct DescribeDomainList {
+    #[serde(rename = "Offset")]
+    pub offset: u32,
+    #[serde(rename = "Limit")]
+    pub limit: u32,
+}
+#[derive(Debug, Deserialize)]
+pub struct DomainInfo {
+    #[serde(rename = "DomainId")]
+    pub id: u64,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Status")]
+    pub status: String,
+}
+#[derive(Serialize)]
 pub struct ModifyRecord<'a> {
     #[serde(rename = "Domain")]
     pub domain: &'a str,
@@ -45,7 +45,8 @@ pub(super) fn create_signature(
     ]
     .join("\n");
 
-    let key = format!("TC3{}", config.secret_key);
+    // the client resolved the secrets
+    let key = format!("TC3{}", config.secret_key.0);
     let sig_date: [u8; 32] = hmac_sha256(key, date)?;
     let sig_svc: [u8; 32] = hmac_sha256(sig_date, "dnspod")?;
     let key: [u8; 32] = hmac_sha256(sig_svc, "tc3_request")?;
@@ -53,7 +54,7 @@ pub(super) fn create_signature(
 
     let authorization = format!(
         "TC3-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
-        &config.secret_id, &credit_scope, &signed_header_names, &sig
+        config.secret_id.0, &credit_scope, &signed_header_names, &sig
     );
     Ok(authorization)
 }
@@ -61,13 +62,16 @@ pub(super) fn create_signature(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::provider::tencent::{Config, client::schema::DescribeRecordList};
+    use crate::{
+        model::Secret,
+        provider::tencent::{Config, client::schema::DescribeRecordList},
+    };
 
     #[test]
     fn test_signature() {
         let config = Config {
-            secret_id: "AKID********************************".to_string(),
-            secret_key: "********************************".to_string(),
+            secret_id: Secret("AKID********************************".to_string()),
+            secret_key: Secret("********************************".to_string()),
         };
         let time = 1551113065;
         let data = serde_json::to_string(&DescribeRecordList {
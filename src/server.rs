@@ -1,21 +1,82 @@
-use crate::{api, cli::RunArgs, service::AppCtx, website};
+use crate::{
+    api,
+    cli::RunArgs,
+    service::{AppCtx, store::StoreOptions, token::LocalToken},
+    website,
+};
 use axum::Router;
-use tokio::{net::TcpListener, signal::ctrl_c};
-use tracing::info;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::{fs::remove_file, net::TcpListener, signal::ctrl_c};
+use tracing::{info, warn};
 
 pub async fn run(args: RunArgs) -> anyhow::Result<()> {
-    let path = args.config();
-    let ctx = AppCtx::new(path).await?;
-    let listener = TcpListener::bind(&ctx.store.get_listen().await).await?;
+    let RunArgs {
+        location,
+        listen,
+        log_level,
+        config_data,
+        read_only,
+    } = args;
+    let options = StoreOptions {
+        data_dir: location.data_dir.clone(),
+        content: config_data,
+        read_only,
+    };
+    let ctx = AppCtx::new(location.config(), options, log_level).await?;
+    let listen = match listen {
+        Some(listen) => listen,
+        None => ctx.store.get_listen().await,
+    };
+    let listener = TcpListener::bind(&listen).await?;
+    let addr = listener.local_addr()?;
+    let token_file = ctx.store.token_file();
+    let store = ctx.store.clone();
+    if let Err(e) = local_token(&ctx, addr).write(&token_file).await {
+        warn!("the cli can't use the api: {e:#}");
+    }
     let router = app(ctx).await?;
-    info!("server listen at {}", listener.local_addr()?);
+    info!("server listen at {addr}");
     axum::serve(listener, router)
         .with_graceful_shutdown(shutdown())
         .await?;
     info!("server is shutting down...");
+    if let Err(e) = store.persist_states().await {
+        warn!("save dns states fail: {e:#}");
+    }
+    let _ = remove_file(&token_file).await;
     Ok(())
 }
+
+/// the cli connects through the loopback address when listening on all of them
+fn local_token(ctx: &AppCtx, mut addr: SocketAddr) -> LocalToken {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+        IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+        _ => {}
+    }
+    LocalToken {
+        url: format!("http://{addr}"),
+        token: BASE64_STANDARD.encode(ctx.token.generate_local_token()),
+    }
+}
+/// on ctrl-c, or SIGTERM from systemd and docker, so the token file is removed
 async fn shutdown() -> () {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = ctrl_c() => {}
+                _ = terminate.recv() => info!("SIGTERM received"),
+            },
+            Err(e) => {
+                warn!("listen SIGTERM fail: {e}");
+                ctrl_c().await.expect("error on await ctrl_c");
+            }
+        }
+    }
+    #[cfg(not(unix))]
     ctrl_c().await.expect("error on await ctrl_c");
 }
 
@@ -0,0 +1,285 @@
+use crate::{
+    model::{AddrConfig, Family, Family::Ipv4, Method, Suffix},
+    service::retry::http_client,
+};
+use anyhow::bail;
+use serde::Serialize;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+use tokio::process::Command;
+
+pub use cache::DetectCache;
+
+mod cache;
+mod dns;
+mod inet6;
+mod natpmp;
+mod upnp;
+
+/// every candidate address reported by `method`
+async fn probe(method: &Method, family: Family) -> anyhow::Result<Vec<IpAddr>> {
+    let addr = match method {
+        Method::Api { endpoint } => {
+            let data = http_client()
+                .build()?
+                .get(endpoint)
+                .header("User-Agent", "curl/0.0.0")
+                .send()
+                .await?
+                .bytes()
+                .await?;
+            let ip = String::from_utf8(data.to_vec())?;
+            let addr = IpAddr::from_str(&ip)?;
+            vec![addr]
+        }
+        Method::Nic { interface } => local_ip_address::list_afinet_netifas()?
+            .into_iter()
+            .filter_map(|(name, addr)| (name == *interface).then_some(addr))
+            .collect::<Vec<IpAddr>>(),
+        Method::Cmd { command } => {
+            // killed when the detection times out
+            let output = Command::new("sh")
+                .args(["-c", command])
+                .kill_on_drop(true)
+                .output()
+                .await?;
+            let addr = String::from_utf8(output.stdout.to_vec())?;
+            let addr = IpAddr::from_str(addr.trim())?;
+            vec![addr]
+        }
+        Method::Dns {
+            server,
+            query,
+            record,
+            class,
+        } => dns::resolve(server, query, *record, *class, family).await?,
+        Method::Upnp { location } => vec![upnp::external_addr(location).await?],
+        Method::NatPmp { gateway } => vec![natpmp::external_addr(gateway).await?],
+    };
+    Ok(addr)
+}
+
+/// the address of `family` to publish, combined with `suffix` if any
+fn select(
+    addr: Vec<IpAddr>,
+    suffix: Option<&Suffix>,
+    family: Family,
+) -> anyhow::Result<Option<IpAddr>> {
+    let addr = addr
+        .into_iter()
+        .find(|addr| skipped(addr, suffix.is_some(), family).is_none());
+    match suffix {
+        Some(suffix) => addr.map(|addr| combine(addr, suffix)).transpose(),
+        None => Ok(addr),
+    }
+}
+
+/// why `addr` can't be published, the first address that can is chosen
+fn skipped(addr: &IpAddr, suffix: bool, family: Family) -> Option<&'static str> {
+    if addr.is_ipv4() != (family == Ipv4) {
+        return Some("other family");
+    }
+    // the prefix of a link-local or loopback address is never the delegated one
+    if suffix && matches!(Scope::of(addr), Scope::Loopback | Scope::LinkLocal) {
+        return Some("no prefix for the suffix");
+    }
+    None
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Scope {
+    Loopback,
+    LinkLocal,
+    /// rfc 1918
+    Private,
+    /// carrier-grade nat, `100.64.0.0/10`
+    Shared,
+    /// `fc00::/7`
+    UniqueLocal,
+    Global,
+}
+
+impl Scope {
+    pub fn of(addr: &IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(addr) if addr.is_loopback() => Self::Loopback,
+            IpAddr::V4(addr) if addr.is_link_local() => Self::LinkLocal,
+            IpAddr::V4(addr) if addr.is_private() => Self::Private,
+            IpAddr::V4(addr) if addr.octets()[0] == 100 && addr.octets()[1] & 0xc0 == 64 => {
+                Self::Shared
+            }
+            IpAddr::V6(addr) if addr.is_loopback() => Self::Loopback,
+            IpAddr::V6(addr) if addr.is_unicast_link_local() => Self::LinkLocal,
+            IpAddr::V6(addr) if addr.is_unique_local() => Self::UniqueLocal,
+            _ => Self::Global,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Loopback => "loopback",
+            Self::LinkLocal => "link-local",
+            Self::Private => "private",
+            Self::Shared => "shared",
+            Self::UniqueLocal => "unique-local",
+            Self::Global => "global",
+        })
+    }
+}
+
+/// an address reported by a method, see [`explain`]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize)]
+pub struct Candidate {
+    pub addr: IpAddr,
+    pub scope: Scope,
+    /// kernel flags of nic ipv6 addresses on linux, e.g. `temporary`, `deprecated`, `dynamic`.
+    /// only shown, the choice does not depend on them
+    pub flags: Option<Vec<&'static str>>,
+    /// none for the chosen one
+    pub skipped: Option<&'static str>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize)]
+pub struct Explained {
+    pub candidates: Vec<Candidate>,
+    /// the published address, combined with the suffix if any
+    pub chosen: Option<IpAddr>,
+}
+
+/// every address `config` reports and the one [`DetectCache::find_addr`] would choose
+pub async fn explain(config: &AddrConfig, family: Family) -> anyhow::Result<Explained> {
+    let addrs = probe(&config.method, family).await?;
+    let chosen = select(addrs.clone(), config.suffix.as_ref(), family)?;
+    let mut candidates = candidates(addrs, config.suffix.is_some(), family);
+    if let Method::Nic { interface } = &config.method
+        && family == Family::Ipv6
+    {
+        let flags = inet6::flags(interface).await;
+        for candidate in &mut candidates {
+            if let IpAddr::V6(addr) = candidate.addr {
+                candidate.flags = flags.get(&addr).cloned();
+            }
+        }
+    }
+    Ok(Explained { candidates, chosen })
+}
+
+fn candidates(addrs: Vec<IpAddr>, suffix: bool, family: Family) -> Vec<Candidate> {
+    let mut found = false;
+    addrs
+        .into_iter()
+        .map(|addr| {
+            let skipped = skipped(&addr, suffix, family).or_else(|| {
+                let earlier = found.then_some("an earlier address is chosen");
+                found = true;
+                earlier
+            });
+            Candidate {
+                addr,
+                scope: Scope::of(&addr),
+                flags: None,
+                skipped,
+            }
+        })
+        .collect()
+}
+
+/// `prefix` bits of `addr` followed by the host bits of `suffix`
+fn combine(addr: IpAddr, suffix: &Suffix) -> anyhow::Result<IpAddr> {
+    let addr = match (addr, suffix.addr) {
+        (IpAddr::V4(addr), IpAddr::V4(host)) => {
+            let mask = u32::MAX.checked_shl(32 - suffix.prefix as u32).unwrap_or(0);
+            let bits = (u32::from(addr) & mask) | (u32::from(host) & !mask);
+            IpAddr::V4(Ipv4Addr::from(bits))
+        }
+        (IpAddr::V6(addr), IpAddr::V6(host)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - suffix.prefix as u32)
+                .unwrap_or(0);
+            let bits = (u128::from(addr) & mask) | (u128::from(host) & !mask);
+            IpAddr::V6(Ipv6Addr::from(bits))
+        }
+        (addr, host) => bail!("suffix {host} does not match the family of {addr}"),
+    };
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Family::Ipv6;
+
+    #[test]
+    fn combine_suffix() {
+        let suffix: Suffix = "::10".parse().unwrap();
+        assert_eq!(suffix.prefix, 64);
+        let addr = IpAddr::from_str("2001:db8:1:2:a:b:c:d").unwrap();
+        assert_eq!(
+            combine(addr, &suffix).unwrap(),
+            IpAddr::from_str("2001:db8:1:2::10").unwrap()
+        );
+
+        let suffix: Suffix = "0:0:0:1::10/56".parse().unwrap();
+        assert_eq!(
+            combine(addr, &suffix).unwrap(),
+            IpAddr::from_str("2001:db8:1:1::10").unwrap()
+        );
+
+        let suffix: Suffix = "0.0.0.8/24".parse().unwrap();
+        let addr = IpAddr::from([192, 0, 2, 77]);
+        assert_eq!(
+            combine(addr, &suffix).unwrap(),
+            IpAddr::from([192, 0, 2, 8])
+        );
+        assert!(combine(IpAddr::from_str("2001:db8::1").unwrap(), &suffix).is_err());
+    }
+
+    #[test]
+    fn explain_candidates() {
+        let addrs = ["fe80::1", "192.168.1.2", "2001:db8::1", "fd00::1"]
+            .map(|addr| IpAddr::from_str(addr).unwrap())
+            .to_vec();
+        let candidates = candidates(addrs.clone(), true, Ipv6);
+        let skipped: Vec<_> = candidates.iter().map(|c| c.skipped).collect();
+        assert_eq!(
+            skipped,
+            [
+                Some("no prefix for the suffix"),
+                Some("other family"),
+                None,
+                Some("an earlier address is chosen")
+            ]
+        );
+        let scopes: Vec<_> = candidates.iter().map(|c| c.scope).collect();
+        assert_eq!(
+            scopes,
+            [
+                Scope::LinkLocal,
+                Scope::Private,
+                Scope::Global,
+                Scope::UniqueLocal
+            ]
+        );
+        // without a suffix the link-local address is published
+        assert_eq!(
+            select(addrs, None, Ipv6).unwrap(),
+            Some(IpAddr::from_str("fe80::1").unwrap())
+        );
+        assert_eq!(Scope::of(&IpAddr::from([100, 100, 0, 1])), Scope::Shared);
+    }
+
+    #[test]
+    fn parse_suffix() {
+        assert!("0.0.0.8".parse::<Suffix>().is_err());
+        assert!("::10/129".parse::<Suffix>().is_err());
+        assert_eq!(String::from("::10".parse::<Suffix>().unwrap()), "::10/64");
+    }
+}
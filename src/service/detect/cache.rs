@@ -0,0 +1,116 @@
+use crate::{
+    model::{AddrConfig, Family, Method},
+    service::detect::{probe, select},
+};
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use time::UtcDateTime;
+
+/// results younger than this are shared by every config using the same method
+const TTL: Duration = Duration::from_secs(10);
+
+/// the addresses reported by one method, shared across configs
+#[derive(Default)]
+pub struct DetectCache {
+    entries: papaya::HashMap<(Method, Family), Arc<Entry>>,
+}
+
+#[derive(Default)]
+struct Entry {
+    /// held while probing, so concurrent callers wait for one probe
+    probing: tokio::sync::Mutex<()>,
+    last: Mutex<Option<Probe>>,
+}
+
+#[derive(Clone)]
+pub struct Probe {
+    pub addrs: Vec<IpAddr>,
+    /// unix timestamp
+    pub timestamp: u64,
+    instant: Instant,
+}
+
+impl DetectCache {
+    /// the address of `config`, reusing a probe made within the TTL and after `since`
+    pub async fn find_addr(
+        &self,
+        config: &AddrConfig,
+        family: Family,
+        since: Option<Instant>,
+    ) -> anyhow::Result<Option<IpAddr>> {
+        let addrs = self.probe(&config.method, family, since).await?;
+        select(addrs, config.suffix.as_ref(), family)
+    }
+
+    async fn probe(
+        &self,
+        method: &Method,
+        family: Family,
+        since: Option<Instant>,
+    ) -> anyhow::Result<Vec<IpAddr>> {
+        let entry = self
+            .entries
+            .pin()
+            .get_or_insert_with((method.clone(), family), Default::default)
+            .clone();
+        let _probing = entry.probing.lock().await;
+        if let Some(last) = entry.last.lock().unwrap().as_ref()
+            && last.instant.elapsed() < TTL
+            && since.is_none_or(|since| last.instant >= since)
+        {
+            return Ok(last.addrs.clone());
+        }
+        // failures are not cached, the next caller probes again
+        let addrs = probe(method, family).await?;
+        *entry.last.lock().unwrap() = Some(Probe {
+            addrs: addrs.clone(),
+            timestamp: UtcDateTime::now().unix_timestamp() as _,
+            instant: Instant::now(),
+        });
+        Ok(addrs)
+    }
+
+    /// the latest successful probe of `method`
+    pub fn last(&self, method: &Method, family: Family) -> Option<Probe> {
+        let entries = self.entries.pin();
+        let entry = entries.get(&(method.clone(), family))?;
+        entry.last.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn share_probe() {
+        let cache = DetectCache::default();
+        let config = AddrConfig {
+            enabled: true,
+            method: Method::Cmd {
+                command: "echo 192.0.2.1".to_string(),
+            },
+            suffix: None,
+            state: None,
+        };
+        let addr = cache.find_addr(&config, Family::Ipv4, None).await.unwrap();
+        assert_eq!(addr, Some(IpAddr::from([192, 0, 2, 1])));
+        let first = cache.last(&config.method, Family::Ipv4).unwrap().instant;
+
+        cache.find_addr(&config, Family::Ipv4, None).await.unwrap();
+        let last = cache.last(&config.method, Family::Ipv4).unwrap().instant;
+        assert_eq!(first, last);
+
+        // a change after the last probe forces a new one
+        cache
+            .find_addr(&config, Family::Ipv4, Some(Instant::now()))
+            .await
+            .unwrap();
+        let last = cache.last(&config.method, Family::Ipv4).unwrap().instant;
+        assert!(last > first);
+        assert!(cache.last(&config.method, Family::Ipv6).is_none());
+    }
+}
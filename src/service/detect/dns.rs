@@ -0,0 +1,295 @@
+//! A tiny DNS client for "whoami" style lookups, e.g.
+//! `myip.opendns.com` @ `resolver1.opendns.com`,
+//! TXT `o-o.myaddr.l.google.com` @ `ns1.google.com`,
+//! CH TXT `whoami.cloudflare` @ `1.1.1.1`.
+use crate::model::{Family, QueryClass, RecordType};
+use anyhow::{Context, anyhow, bail};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    net::{UdpSocket, lookup_host},
+    time::timeout,
+};
+
+const PORT: u16 = 53;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_A: u16 = 1;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+
+const CLASS_IN: u16 = 1;
+const CLASS_CH: u16 = 3;
+
+pub(super) async fn resolve(
+    server: &str,
+    query: &str,
+    record: RecordType,
+    class: QueryClass,
+    family: Family,
+) -> anyhow::Result<Vec<IpAddr>> {
+    let server = server_addr(server, family).await?;
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+
+    let qtype = match (record, family) {
+        (RecordType::Addr, Family::Ipv4) => TYPE_A,
+        (RecordType::Addr, Family::Ipv6) => TYPE_AAAA,
+        (RecordType::Txt, _) => TYPE_TXT,
+    };
+    let qclass = match class {
+        QueryClass::In => CLASS_IN,
+        QueryClass::Ch => CLASS_CH,
+    };
+    let id = rand::random::<u16>();
+    socket.send(&encode(id, query, qtype, qclass)?).await?;
+
+    let exchange = async {
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            // stray datagrams (late answers of earlier queries) are ignored
+            if let Some(addrs) = decode(id, &buf[..len])? {
+                return anyhow::Ok(addrs);
+            }
+        }
+    };
+    timeout(TIMEOUT, exchange)
+        .await
+        .map_err(|_| anyhow!("dns query to {server} timed out"))?
+}
+
+/// `host`, `host:port`, `ip` or `ip:port`.
+/// prefers a server address of the same family, so the resolver sees the address we are looking for.
+async fn server_addr(server: &str, family: Family) -> anyhow::Result<SocketAddr> {
+    let server = server.trim();
+    if server.is_empty() {
+        bail!("dns server is empty");
+    }
+    if let Ok(addr) = SocketAddr::from_str(server) {
+        return Ok(addr);
+    }
+    if let Ok(addr) = IpAddr::from_str(server) {
+        return Ok((addr, PORT).into());
+    }
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>()?),
+        None => (server, PORT),
+    };
+    let addrs: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+    let matched = addrs.iter().find(|addr| match family {
+        Family::Ipv4 => addr.is_ipv4(),
+        Family::Ipv6 => addr.is_ipv6(),
+    });
+    matched
+        .or(addrs.first())
+        .copied()
+        .with_context(|| format!("cannot resolve dns server {server}"))
+}
+
+fn encode(id: u16, name: &str, qtype: u16, qclass: u16) -> anyhow::Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(12 + name.len() + 6);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // standard query, recursion desired
+    packet.extend_from_slice(&0x0100u16.to_be_bytes());
+    // qdcount, ancount, nscount, arcount
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid dns name: {name}");
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&qclass.to_be_bytes());
+    Ok(packet)
+}
+
+/// `None` if the packet is not the response of query `id`.
+fn decode(id: u16, packet: &[u8]) -> anyhow::Result<Option<Vec<IpAddr>>> {
+    let mut reader = Reader { packet, pos: 0 };
+    let (response_id, flags) = (reader.u16()?, reader.u16()?);
+    if response_id != id || flags & 0x8000 == 0 {
+        return Ok(None);
+    }
+    if flags & 0x0200 != 0 {
+        bail!("dns response truncated");
+    }
+    match flags & 0x000F {
+        0 => {}
+        2 => bail!("dns server failure"),
+        3 => bail!("dns name does not exist"),
+        5 => bail!("dns query refused"),
+        code => bail!("dns response error. rcode: {code}"),
+    }
+    let (questions, answers) = (reader.u16()?, reader.u16()?);
+    reader.take(4)?;
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+    let mut addrs = vec![];
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let ty = reader.u16()?;
+        reader.take(6)?;
+        let len = reader.u16()? as usize;
+        let data = reader.take(len)?;
+        match (ty, len) {
+            (TYPE_A, 4) => addrs.push(IpAddr::from(<[u8; 4]>::try_from(data)?)),
+            (TYPE_AAAA, 16) => addrs.push(IpAddr::from(<[u8; 16]>::try_from(data)?)),
+            (TYPE_TXT, _) => {
+                let mut txt = Reader {
+                    packet: data,
+                    pos: 0,
+                };
+                while txt.pos < data.len() {
+                    let len = txt.u8()? as usize;
+                    let value = String::from_utf8_lossy(txt.take(len)?);
+                    if let Ok(addr) = IpAddr::from_str(value.trim()) {
+                        addrs.push(addr);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(addrs))
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let data = self
+            .packet
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("malformed dns response"))?;
+        self.pos += len;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn skip_name(&mut self) -> anyhow::Result<()> {
+        loop {
+            match self.u8()? {
+                0 => return Ok(()),
+                // compression pointer terminates the name
+                len if len & 0xC0 == 0xC0 => {
+                    self.take(1)?;
+                    return Ok(());
+                }
+                len => {
+                    self.take(len as usize)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// answers every query with one record of `ty`, or with `rcode` if it is not 0
+    async fn responder(ty: u16, rdata: Vec<u8>, rcode: u8) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let mut resp = buf[..len].to_vec();
+            resp[2] = 0x81;
+            resp[3] = 0x80 | rcode;
+            if rcode == 0 {
+                resp[7] = 1;
+                resp.extend_from_slice(&[0xC0, 0x0C]);
+                resp.extend_from_slice(&ty.to_be_bytes());
+                resp.extend_from_slice(&CLASS_IN.to_be_bytes());
+                resp.extend_from_slice(&60u32.to_be_bytes());
+                resp.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                resp.extend_from_slice(&rdata);
+            }
+            socket.send_to(&resp, peer).await.unwrap();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn addr_record() {
+        let server = responder(TYPE_A, vec![203, 0, 113, 7], 0).await;
+        let addrs = resolve(
+            &server.to_string(),
+            "myip.opendns.com",
+            RecordType::Addr,
+            QueryClass::In,
+            Family::Ipv4,
+        )
+        .await
+        .unwrap();
+        assert_eq!(addrs, vec![IpAddr::from([203, 0, 113, 7])]);
+    }
+
+    #[tokio::test]
+    async fn txt_record() {
+        let mut rdata = vec![];
+        for value in ["2001:db8::1", "edns0-client-subnet 192.0.2.0/24"] {
+            rdata.push(value.len() as u8);
+            rdata.extend_from_slice(value.as_bytes());
+        }
+        let server = responder(TYPE_TXT, rdata, 0).await;
+        let addrs = resolve(
+            &server.to_string(),
+            "o-o.myaddr.l.google.com",
+            RecordType::Txt,
+            QueryClass::In,
+            Family::Ipv6,
+        )
+        .await
+        .unwrap();
+        assert_eq!(addrs, vec![IpAddr::from_str("2001:db8::1").unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn name_error() {
+        let server = responder(TYPE_A, vec![], 3).await;
+        let e = resolve(
+            &server.to_string(),
+            "myip.opendns.com",
+            RecordType::Addr,
+            QueryClass::In,
+            Family::Ipv4,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.to_string(), "dns name does not exist");
+    }
+
+    #[test]
+    fn encode_query() {
+        let packet = encode(0x1234, "whoami.cloudflare.", TYPE_TXT, CLASS_CH).unwrap();
+        assert_eq!(&packet[..2], &[0x12, 0x34]);
+        assert_eq!(&packet[12..19], b"\x06whoami");
+        assert_eq!(&packet[packet.len() - 4..], &[0, 16, 0, 3]);
+        assert!(encode(0, "a..b", TYPE_A, CLASS_IN).is_err());
+    }
+}
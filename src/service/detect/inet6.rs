@@ -0,0 +1,76 @@
+//! Kernel flags of IPv6 interface addresses, read from `/proc/net/if_inet6` on Linux.
+
+use std::{collections::HashMap, net::Ipv6Addr};
+
+/// see `IFA_F_*` in `linux/if_addr.h`
+const FLAGS: [(u32, &str); 4] = [
+    (0x01, "temporary"),
+    (0x08, "dadfailed"),
+    (0x20, "deprecated"),
+    (0x40, "tentative"),
+];
+const PERMANENT: u32 = 0x80;
+
+/// the flags of the addresses of `interface` as `ip addr` shows them, empty on other systems
+pub async fn flags(interface: &str) -> HashMap<Ipv6Addr, Vec<&'static str>> {
+    #[cfg(target_os = "linux")]
+    if let Ok(content) = tokio::fs::read_to_string("/proc/net/if_inet6").await {
+        return parse(&content, interface);
+    }
+    HashMap::new()
+}
+
+/// lines of `address index prefix scope flags name` in hex
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse(content: &str, interface: &str) -> HashMap<Ipv6Addr, Vec<&'static str>> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [addr, _, _, _, flags, name] = fields[..] else {
+                return None;
+            };
+            if name != interface {
+                return None;
+            }
+            let addr = Ipv6Addr::from(u128::from_str_radix(addr, 16).ok()?);
+            let flags = u32::from_str_radix(flags, 16).ok()?;
+            let mut names: Vec<_> = FLAGS
+                .iter()
+                .filter(|(bit, _)| flags & bit != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            if flags & PERMANENT == 0 {
+                names.push("dynamic");
+            }
+            Some((addr, names))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_inet6() {
+        let content = "\
+fe800000000000000000000000000001 02 40 20 80     eth0
+20010db8000000000000000000000001 02 40 00 80     eth0
+20010db8000000001234567890abcdef 02 40 00 21     eth0
+20010db8000000000000000000000002 02 40 00 00     eth0
+00000000000000000000000000000001 01 80 10 80       lo
+";
+        let flags = parse(content, "eth0");
+        assert_eq!(flags.len(), 4);
+        let addr = |addr: &str| addr.parse::<Ipv6Addr>().unwrap();
+        assert!(flags[&addr("fe80::1")].is_empty());
+        assert!(flags[&addr("2001:db8::1")].is_empty());
+        assert_eq!(
+            flags[&addr("2001:db8::1234:5678:90ab:cdef")],
+            ["temporary", "deprecated", "dynamic"]
+        );
+        assert_eq!(flags[&addr("2001:db8::2")], ["dynamic"]);
+        assert!(parse(content, "eth1").is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! NAT-PMP (RFC 6886) public address request.
+use anyhow::{anyhow, bail};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+const PORT: u16 = 5351;
+/// initial retransmission timeout, doubled after every attempt
+const TIMEOUT: Duration = Duration::from_millis(250);
+const ATTEMPTS: u32 = 4;
+
+pub(super) async fn external_addr(gateway: &str) -> anyhow::Result<IpAddr> {
+    let gateway = match gateway.trim() {
+        "" => SocketAddr::new(default_gateway()?.into(), PORT),
+        gateway => match SocketAddr::from_str(gateway) {
+            Ok(addr) => addr,
+            Err(_) => SocketAddr::new(IpAddr::from_str(gateway)?, PORT),
+        },
+    };
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(gateway).await?;
+    let mut buf = [0u8; 16];
+    for attempt in 0..ATTEMPTS {
+        // version 0, opcode 0: public address request
+        socket.send(&[0, 0]).await?;
+        match timeout(TIMEOUT * 2u32.pow(attempt), socket.recv(&mut buf)).await {
+            Ok(len) => return parse(&buf[..len?]),
+            Err(_) => continue,
+        }
+    }
+    Err(anyhow!("NAT-PMP gateway {gateway} did not respond"))
+}
+
+fn parse(response: &[u8]) -> anyhow::Result<IpAddr> {
+    let [0, 128, hi, lo, _, _, _, _, a, b, c, d] = response else {
+        bail!("invalid NAT-PMP response");
+    };
+    match u16::from_be_bytes([*hi, *lo]) {
+        0 => Ok(Ipv4Addr::new(*a, *b, *c, *d).into()),
+        1 => bail!("NAT-PMP: unsupported version"),
+        2 => bail!("NAT-PMP: not authorized/refused"),
+        3 => bail!("NAT-PMP: network failure"),
+        4 => bail!("NAT-PMP: out of resources"),
+        code => bail!("NAT-PMP: result code {code}"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_gateway() -> anyhow::Result<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let [_, "00000000", gateway, ..] = fields[..]
+            && gateway != "00000000"
+        {
+            // printed in host byte order
+            return Ok(u32::from_str_radix(gateway, 16)?.to_ne_bytes().into());
+        }
+    }
+    bail!("cannot find default gateway")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> anyhow::Result<Ipv4Addr> {
+    bail!("gateway address is required")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn public_address() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 16];
+            // drop the first request to exercise retransmission
+            socket.recv_from(&mut buf).await.unwrap();
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &[0, 0]);
+            let response = [0, 128, 0, 0, 0, 0, 0x0e, 0x10, 203, 0, 113, 9];
+            socket.send_to(&response, peer).await.unwrap();
+        });
+        let addr = external_addr(&gateway.to_string()).await.unwrap();
+        assert_eq!(addr, IpAddr::from([203, 0, 113, 9]));
+    }
+
+    #[test]
+    fn refused() {
+        let e = parse(&[0, 128, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err();
+        assert_eq!(e.to_string(), "NAT-PMP: not authorized/refused");
+        assert!(parse(&[0, 129, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! UPnP IGD: SSDP discovery + `GetExternalIPAddress` SOAP call.
+use anyhow::{Context, anyhow, bail};
+use reqwest::{Client, header::CONTENT_TYPE};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::timeout};
+use url::Url;
+
+const SSDP_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+const SERVICES: &[&str] = &[
+    "urn:schemas-upnp-org:service:WANIPConnection:",
+    "urn:schemas-upnp-org:service:WANPPPConnection:",
+];
+
+pub(super) async fn external_addr(location: &str) -> anyhow::Result<IpAddr> {
+    let location = match location.trim() {
+        "" => discover(SSDP_ADDR).await?,
+        location => location.to_string(),
+    };
+    let client = Client::builder().timeout(TIMEOUT).build()?;
+    let description = client
+        .get(&location)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let (service, control) = control_url(&description)
+        .ok_or_else(|| anyhow!("no WANIPConnection/WANPPPConnection service at {location}"))?;
+    let control = Url::parse(&location)?.join(control)?;
+    let body = format!(
+        r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:GetExternalIPAddress xmlns:u="{service}"></u:GetExternalIPAddress></s:Body></s:Envelope>"#
+    );
+    let response = client
+        .post(control)
+        .header(CONTENT_TYPE, r#"text/xml; charset="utf-8""#)
+        .header("SOAPAction", format!(r#""{service}#GetExternalIPAddress""#))
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let addr = tag(&response, "NewExternalIPAddress")
+        .ok_or_else(|| anyhow!("invalid GetExternalIPAddress response"))?;
+    IpAddr::from_str(addr.trim()).with_context(|| format!("invalid external address: {addr}"))
+}
+
+/// M-SEARCH for an internet gateway device and return its `LOCATION`
+async fn discover(target: SocketAddr) -> anyhow::Result<String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {SSDP_ADDR}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {SEARCH_TARGET}\r\n\r\n"
+    );
+    socket.send_to(request.as_bytes(), target).await?;
+    let search = async {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (len, _) = socket.recv_from(&mut buf).await?;
+            if let Some(location) = header(&String::from_utf8_lossy(&buf[..len]), "location") {
+                return anyhow::Ok(location.to_string());
+            }
+        }
+    };
+    match timeout(TIMEOUT, search).await {
+        Ok(location) => location,
+        Err(_) => bail!("no UPnP gateway answered the SSDP search"),
+    }
+}
+
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// (service type, control url) of the first WAN connection service
+fn control_url(description: &str) -> Option<(&str, &str)> {
+    description.split("<service>").skip(1).find_map(|service| {
+        let ty = tag(service, "serviceType")?.trim();
+        let control = tag(service, "controlURL")?.trim();
+        SERVICES
+            .iter()
+            .any(|prefix| ty.starts_with(prefix))
+            .then_some((ty, control))
+    })
+}
+
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let begin = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = begin + xml[begin..].find(&format!("</{name}>"))?;
+    Some(&xml[begin..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        http::HeaderMap,
+        routing::{get, post},
+    };
+    use tokio::net::TcpListener;
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+        <controlURL>/ctl/IPConn</controlURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+
+    async fn gateway() -> SocketAddr {
+        let router = Router::new()
+            .route("/rootDesc.xml", get(|| async { DESCRIPTION }))
+            .route(
+                "/ctl/IPConn",
+                post(|headers: HeaderMap| async move {
+                    assert_eq!(
+                        headers["SOAPAction"],
+                        r#""urn:schemas-upnp-org:service:WANIPConnection:1#GetExternalIPAddress""#
+                    );
+                    r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1"><NewExternalIPAddress>198.51.100.20</NewExternalIPAddress></u:GetExternalIPAddressResponse></s:Body></s:Envelope>"#
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn soap() {
+        let gateway = gateway().await;
+        let addr = external_addr(&format!("http://{gateway}/rootDesc.xml"))
+            .await
+            .unwrap();
+        assert_eq!(addr, IpAddr::from([198, 51, 100, 20]));
+    }
+
+    #[tokio::test]
+    async fn ssdp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            assert!(request.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+            assert_eq!(header(&request, "st"), Some(SEARCH_TARGET));
+            let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+            socket.send_to(response.as_bytes(), peer).await.unwrap();
+        });
+        let location = discover(target).await.unwrap();
+        assert_eq!(location, "http://192.168.1.1:5000/rootDesc.xml");
+    }
+}
@@ -1,43 +1,366 @@
 use crate::{
-    model::DnsConfig,
-    service::{store::StoreService, task::spawn_ddns_updating_task},
+    model::{DnsConfig, RestartPolicy},
+    service::{
+        detect::DetectCache,
+        netlink::NetlinkService,
+        retry::Backoff,
+        store::StoreService,
+        task::{Command, run_ddns_updating_task},
+    },
 };
-use std::sync::Arc;
-use tokio::task::JoinHandle;
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use time::UtcDateTime;
+use tokio::{
+    sync::{Mutex as AsyncMutex, mpsc},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{error, info};
+
+/// commands queued for a task, a run request is rejected when full, a reload waits
+const COMMANDS: usize = 8;
+/// first delay before restarting a task, doubled up to `RESTART_MAX`
+const RESTART_BASE: Duration = Duration::from_secs(5);
+const RESTART_MAX: Duration = Duration::from_secs(300);
+
+struct Task {
+    /// the supervisor, aborting it aborts the task
+    handle: JoinHandle<()>,
+    status: Arc<TaskStatus>,
+    commands: mpsc::Sender<Command>,
+}
+
+#[derive(Serialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RunState {
+    /// updating the records
+    Running,
+    /// waiting for the next run, or paused
+    #[default]
+    Idle,
+    /// stopped and not restarted
+    Finished,
+    /// panicked, restarted unless the restart policy is `never`
+    Crashed,
+}
 
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskState {
+    pub state: RunState,
+    /// updates run since the task was created
+    pub runs: u64,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// unix timestamp of the next scheduled run
+    pub next_run: Option<u64>,
+    /// unix timestamp of the creation
+    pub created: u64,
+}
+
+/// shared between the task, its supervisor and the api
 #[derive(Default)]
+pub struct TaskStatus(Mutex<TaskState>);
+
+impl TaskStatus {
+    pub fn get(&self) -> TaskState {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut TaskState)) {
+        f(&mut self.0.lock().unwrap())
+    }
+}
+
 pub struct TaskManager {
-    handles: papaya::HashMap<String, JoinHandle<()>>,
+    tasks: papaya::HashMap<String, Task>,
+    netlink: Arc<NetlinkService>,
+    detect: Arc<DetectCache>,
 }
-pub async fn start_ddns_sync_svc(store: Arc<StoreService>) -> TaskManager {
-    let manager = TaskManager::default();
+pub async fn start_ddns_sync_svc(
+    store: Arc<StoreService>,
+    netlink: Arc<NetlinkService>,
+    detect: Arc<DetectCache>,
+) -> TaskManager {
+    let manager = TaskManager {
+        tasks: Default::default(),
+        netlink,
+        detect,
+    };
     for config in store.list_dns_configs().await {
-        let name = config.name.clone();
-        let handle = spawn_ddns_updating_task(config, store.clone()).await;
-        manager.handles.pin_owned().insert(name, handle);
+        manager.spawn(config, store.clone(), false);
     }
     manager
 }
 
 impl TaskManager {
-    pub async fn create_task(
-        &self,
-        config: DnsConfig,
-        store: Arc<StoreService>,
-    ) -> anyhow::Result<()> {
+    fn spawn(&self, config: DnsConfig, store: Arc<StoreService>, immediate: bool) {
         let name = config.name.clone();
-        if self.handles.pin_owned().contains_key(&name) {
-            anyhow::bail!("Duplicate task name: {}", &name);
+        let status = Arc::new(TaskStatus::default());
+        status.update(|state| state.created = UtcDateTime::now().unix_timestamp() as _);
+        let (commands, receiver) = mpsc::channel(COMMANDS);
+        let supervisor = Supervisor {
+            name: name.clone(),
+            store,
+            detect: self.detect.clone(),
+            netlink: self.netlink.clone(),
+            commands: Arc::new(AsyncMutex::new(receiver)),
+            status: status.clone(),
+        };
+        let handle = tokio::spawn(supervisor.run(config, immediate));
+        let task = Task {
+            handle,
+            status,
+            commands,
+        };
+        self.tasks.pin().insert(name, task);
+    }
+
+    /// applies the config to the running task, or starts a task updating immediately.
+    /// waits while the task is busy, the config is already saved and must reach it
+    pub async fn reload_task(&self, config: DnsConfig, store: Arc<StoreService>) {
+        // the channel is closed if the task finished meanwhile
+        if let Some(sender) = self.sender(&config.name)
+            && sender
+                .send(Command::Reload(Box::new(config.clone())))
+                .await
+                .is_ok()
+        {
+            return;
         }
-        let handle = spawn_ddns_updating_task(config, store).await;
-        self.handles.pin_owned().insert(name, handle);
-        Ok(())
+        self.spawn(config, store, true);
     }
 
+    /// whether a running task received the command
+    pub fn run_task(&self, name: &str) -> anyhow::Result<bool> {
+        let Some(sender) = self.sender(name) else {
+            return Ok(false);
+        };
+        sender.try_send(Command::RunNow).context("task is busy")?;
+        Ok(true)
+    }
+
+    /// the task finishes its current update before stopping
     pub async fn delete_task(&self, name: &str) {
-        let guard = self.handles.pin_owned();
-        if let Some(handle) = guard.remove(name) {
-            handle.abort();
+        let guard = self.tasks.pin_owned();
+        if let Some(task) = guard.remove(name) {
+            // the channel is closed anyway once the sender is dropped
+            let _ = task.commands.try_send(Command::Stop);
+        }
+    }
+
+    fn sender(&self, name: &str) -> Option<mpsc::Sender<Command>> {
+        let tasks = self.tasks.pin();
+        let task = tasks.get(name)?;
+        (!task.handle.is_finished()).then(|| task.commands.clone())
+    }
+
+    pub fn next_run(&self, name: &str) -> Option<u64> {
+        self.tasks.pin().get(name)?.status.get().next_run
+    }
+
+    pub fn list(&self) -> Vec<(String, TaskState)> {
+        let mut tasks: Vec<_> = self
+            .tasks
+            .pin()
+            .iter()
+            .map(|(name, task)| (name.clone(), task.status.get()))
+            .collect();
+        tasks.sort_by(|(a, _), (b, _)| a.cmp(b));
+        tasks
+    }
+}
+
+/// runs the task and restarts it according to its restart policy
+struct Supervisor {
+    name: String,
+    store: Arc<StoreService>,
+    detect: Arc<DetectCache>,
+    netlink: Arc<NetlinkService>,
+    /// shared by the restarted tasks, so queued commands are not lost
+    commands: Arc<AsyncMutex<mpsc::Receiver<Command>>>,
+    status: Arc<TaskStatus>,
+}
+
+impl Supervisor {
+    async fn run(self, config: DnsConfig, immediate: bool) {
+        let Self {
+            name,
+            store,
+            detect,
+            netlink,
+            commands,
+            status,
+        } = self;
+        let start = |config, immediate| {
+            tokio::spawn(run_ddns_updating_task(
+                config,
+                store.clone(),
+                detect.clone(),
+                netlink.subscribe(),
+                commands.clone(),
+                status.clone(),
+                immediate,
+            ))
+        };
+        supervise(&name, &store, &status, config, immediate, start).await;
+    }
+}
+
+/// runs the task made by `start` and restarts it according to its restart policy
+async fn supervise(
+    name: &str,
+    store: &StoreService,
+    status: &TaskStatus,
+    mut config: DnsConfig,
+    mut immediate: bool,
+    mut start: impl FnMut(DnsConfig, bool) -> JoinHandle<()>,
+) {
+    let mut backoff = Backoff::new(RESTART_BASE, RESTART_MAX);
+    loop {
+        let policy = config.restart.unwrap_or_default();
+        let started = Instant::now();
+        let task = AbortOnDrop(start(config, immediate));
+        let result = task.join().await;
+        status.update(|state| {
+            state.state = match result {
+                Ok(_) => RunState::Finished,
+                Err(_) => RunState::Crashed,
+            };
+            state.next_run = None;
+        });
+        // the task only returns when it is stopped
+        let Err(message) = result else {
+            return;
+        };
+        error!("task {name} crashed: {message}");
+        status.update(|state| state.last_error = Some(message));
+        if policy == RestartPolicy::Never {
+            return;
         }
+        // a task running for a while is not crashing in a loop
+        if started.elapsed() > RESTART_MAX {
+            backoff.reset();
+        }
+        let delay = backoff.next_delay();
+        info!("restart task {name} in {delay:?}");
+        tokio::time::sleep(delay).await;
+        // the config may have been reloaded or deleted since
+        let Some(stored) = store.get_dns_config(name).await else {
+            return;
+        };
+        config = stored;
+        status.update(|state| state.restarts += 1);
+        immediate = false;
+    }
+}
+
+/// aborts the task when the supervisor is aborted
+struct AbortOnDrop(JoinHandle<()>);
+
+impl AbortOnDrop {
+    /// the panic message if the task panicked
+    async fn join(mut self) -> Result<(), String> {
+        match (&mut self.0).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_panic() => Err(panic_message(e.into_panic())),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "task panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::testing::{inline_store, sample_config};
+
+    #[tokio::test]
+    async fn join_panicked() {
+        let task = AbortOnDrop(tokio::spawn(async { panic!("boom") }));
+        assert_eq!(task.join().await, Err("boom".to_string()));
+
+        let task = AbortOnDrop(tokio::spawn(async {}));
+        assert_eq!(task.join().await, Ok(()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_panicked_task() {
+        let content = serde_json::json!({
+            "listen": "0.0.0.0:6789",
+            "ddns": [sample_config("cf")],
+        });
+        let store = inline_store("restart", &content.to_string()).await;
+        let cases = [
+            (RestartPolicy::Never, 1, RunState::Crashed, 0),
+            (RestartPolicy::OnFailure, 2, RunState::Finished, 1),
+        ];
+        for (policy, expected, finished, restarts) in cases {
+            let mut config = store.get_dns_config("home").await.unwrap();
+            config.restart = Some(policy);
+            let status = TaskStatus::default();
+            let mut starts = 0;
+            // the restarted task stops as it would on delete
+            let start = |_, _| {
+                starts += 1;
+                let first = starts == 1;
+                tokio::spawn(async move { assert!(!first, "boom") })
+            };
+            supervise("home", &store, &status, config, false, start).await;
+            assert_eq!(starts, expected, "{policy:?}");
+            let state = status.get();
+            assert_eq!(state.state, finished);
+            assert_eq!(state.restarts, restarts);
+            assert_eq!(state.last_error.as_deref(), Some("boom"));
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_busy_task() {
+        let store = Arc::new(inline_store("busy", "listen: 0.0.0.0:6789").await);
+        let manager = TaskManager {
+            tasks: Default::default(),
+            netlink: NetlinkService::start(),
+            detect: Default::default(),
+        };
+        let (commands, mut receiver) = mpsc::channel(1);
+        commands.try_send(Command::RunNow).unwrap();
+        let task = Task {
+            handle: tokio::spawn(std::future::pending()),
+            status: Default::default(),
+            commands,
+        };
+        manager.tasks.pin().insert("home".to_string(), task);
+        let config: DnsConfig = serde_json::from_value(sample_config("cf")).unwrap();
+
+        // the reload waits until the queued run is taken
+        assert!(manager.run_task("home").is_err());
+        let (_, (first, second)) = tokio::join!(manager.reload_task(config, store), async {
+            (receiver.recv().await, receiver.recv().await)
+        });
+        assert!(matches!(first, Some(Command::RunNow)));
+        assert!(matches!(second, Some(Command::Reload(config)) if config.name == "home"));
     }
 }
@@ -27,12 +27,12 @@ pub struct LogService {
 
 impl LogService {
     const CAPACITY: usize = 256;
-    pub fn init() -> Arc<Self> {
+    pub fn init(level: LevelFilter) -> Arc<Self> {
         let (tx, mut rx) = channel(100);
         tracing_subscriber::registry()
             .with(fmt::layer().with_timer(LocalTime::rfc_3339()))
             .with(MemLayer::new(tx))
-            .with(LevelFilter::INFO)
+            .with(level)
             .init();
         let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(Self::CAPACITY)));
         let svc: Arc<Self> = Self { buffer }.into();
@@ -1,17 +1,25 @@
 use crate::service::{
+    detect::DetectCache,
     dns::{TaskManager, start_ddns_sync_svc},
     log::LogService,
-    store::StoreService,
+    netlink::NetlinkService,
+    store::{StoreOptions, StoreService},
     token::TokenService,
 };
 use std::{path::PathBuf, sync::Arc};
+use tracing::level_filters::LevelFilter;
 
-mod store;
-mod token;
+pub mod store;
+pub mod token;
 
-mod dns;
-mod task;
+mod netlink;
+mod reload;
+pub mod retry;
+pub mod schedule;
+pub mod task;
 
+pub mod detect;
+pub mod dns;
 pub mod log;
 pub mod webhook;
 
@@ -20,20 +28,34 @@ pub struct AppCtx {
     pub store: Arc<StoreService>,
     pub token: Arc<TokenService>,
     pub manager: Arc<TaskManager>,
+    pub detect: Arc<DetectCache>,
     pub log: Arc<LogService>,
 }
 
 impl AppCtx {
-    pub async fn new(file: PathBuf) -> anyhow::Result<Self> {
-        let log = LogService::init().into();
-        let store = Arc::new(StoreService::new(file).await?);
+    pub async fn new(
+        file: PathBuf,
+        options: StoreOptions,
+        log_level: LevelFilter,
+    ) -> anyhow::Result<Self> {
+        let log = LogService::init(log_level);
+        let store = Arc::new(StoreService::new(file, options).await?);
+        store.clone().start_persist_states();
         let token = Arc::new(TokenService::new());
         token.clone().start_evict_expired_token().await;
-        let manager = start_ddns_sync_svc(store.clone()).await.into();
+        let netlink = NetlinkService::start();
+        let detect = Arc::new(DetectCache::default());
+        let manager: Arc<_> = start_ddns_sync_svc(store.clone(), netlink, detect.clone())
+            .await
+            .into();
+        if !store.is_inline() {
+            reload::watch_config(store.clone(), manager.clone());
+        }
         let ctx = Self {
             store,
             token,
             manager,
+            detect,
             log,
         };
         Ok(ctx)
@@ -42,4 +64,20 @@ impl AppCtx {
     pub async fn initialized(&self) -> bool {
         self.store.get_user().await.is_some()
     }
+
+    /// without the log subscriber and the config watcher, which are global
+    #[cfg(test)]
+    pub async fn with_store(store: Arc<StoreService>) -> Self {
+        let detect = Arc::new(DetectCache::default());
+        let manager = start_ddns_sync_svc(store.clone(), NetlinkService::start(), detect.clone());
+        Self {
+            store,
+            token: Arc::new(TokenService::new()),
+            manager: Arc::new(manager.await),
+            detect,
+            log: Arc::new(LogService {
+                buffer: Default::default(),
+            }),
+        }
+    }
 }
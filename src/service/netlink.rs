@@ -0,0 +1,211 @@
+use std::sync::Arc;
+use tokio::sync::broadcast::{Receiver, Sender, channel};
+
+/// broadcasts the name of every interface whose link state or addresses changed.
+/// only backed by rtnetlink on linux, subscribers elsewhere simply never receive anything.
+pub struct NetlinkService {
+    sender: Sender<String>,
+}
+
+impl NetlinkService {
+    const CAPACITY: usize = 64;
+
+    pub fn start() -> Arc<Self> {
+        let (sender, _) = channel(Self::CAPACITY);
+        #[cfg(target_os = "linux")]
+        {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                if let Err(e) = linux::listen(sender).await {
+                    tracing::warn!("netlink monitor unavailable, fall back to polling: {e:#}");
+                }
+            });
+        }
+        Arc::new(Self { sender })
+    }
+
+    pub fn subscribe(&self) -> Receiver<String> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::CStr,
+        io, mem,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    };
+    use tokio::{io::unix::AsyncFd, sync::broadcast::Sender};
+    use tracing::{debug, info, warn};
+
+    const RTM_NEWLINK: u16 = 16;
+    const RTM_DELLINK: u16 = 17;
+    const RTM_NEWADDR: u16 = 20;
+    const RTM_DELADDR: u16 = 21;
+    /// IFLA_IFNAME and IFA_LABEL share the same attribute type
+    const ATTR_NAME: u16 = 3;
+
+    const NLMSG_HDRLEN: usize = 16;
+    const IFINFOMSG_LEN: usize = 16;
+    const IFADDRMSG_LEN: usize = 8;
+
+    pub(super) async fn listen(sender: Sender<String>) -> io::Result<()> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as _;
+        addr.nl_groups =
+            (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as _,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = AsyncFd::new(fd)?;
+        info!("start netlink monitor");
+        let mut buf = vec![0u8; 16 * 1024];
+        loop {
+            let mut guard = fd.readable().await?;
+            let read = guard.try_io(|fd| {
+                let len =
+                    unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+                match len {
+                    ..0 => Err(io::Error::last_os_error()),
+                    len => Ok(len as usize),
+                }
+            });
+            match read {
+                Ok(Ok(len)) => {
+                    for name in parse(&buf[..len], index_to_name) {
+                        debug!("interface changed: {name}");
+                        let _ = sender.send(name);
+                    }
+                }
+                // the kernel dropped messages, nothing to do but keep reading
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    warn!("netlink receive buffer overrun")
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn index_to_name(index: u32) -> Option<String> {
+        let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+        let name = unsafe { libc::if_indextoname(index, buf.as_mut_ptr()) };
+        if name.is_null() {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(name) };
+        Some(name.to_string_lossy().into_owned())
+    }
+
+    /// names of the interfaces mentioned by link and address messages
+    pub(super) fn parse(buf: &[u8], index_to_name: impl Fn(u32) -> Option<String>) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        let mut offset = 0;
+        while let Some(header) = buf.get(offset..offset + NLMSG_HDRLEN) {
+            let len = u32::from_ne_bytes(header[0..4].try_into().unwrap()) as usize;
+            let ty = u16::from_ne_bytes(header[4..6].try_into().unwrap());
+            let Some(message) = buf.get(offset + NLMSG_HDRLEN..offset + len) else {
+                break;
+            };
+            if len < NLMSG_HDRLEN {
+                break;
+            }
+            offset += align(len);
+            let (payload, index) = match ty {
+                RTM_NEWLINK | RTM_DELLINK if message.len() >= IFINFOMSG_LEN => {
+                    let index = u32::from_ne_bytes(message[4..8].try_into().unwrap());
+                    (&message[IFINFOMSG_LEN..], index)
+                }
+                RTM_NEWADDR | RTM_DELADDR if message.len() >= IFADDRMSG_LEN => {
+                    let index = u32::from_ne_bytes(message[4..8].try_into().unwrap());
+                    (&message[IFADDRMSG_LEN..], index)
+                }
+                _ => continue,
+            };
+            let Some(name) = name_attr(payload).or_else(|| index_to_name(index)) else {
+                continue;
+            };
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn name_attr(mut attrs: &[u8]) -> Option<String> {
+        while attrs.len() >= 4 {
+            let len = u16::from_ne_bytes([attrs[0], attrs[1]]) as usize;
+            let ty = u16::from_ne_bytes([attrs[2], attrs[3]]);
+            let value = attrs.get(4..len)?;
+            if ty == ATTR_NAME {
+                let value = value.split(|b| *b == 0).next()?;
+                return Some(String::from_utf8_lossy(value).into_owned());
+            }
+            attrs = attrs.get(align(len)..)?;
+        }
+        None
+    }
+
+    #[inline]
+    fn align(len: usize) -> usize {
+        (len + 3) & !3
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::linux::parse;
+
+    fn message(ty: u16, body: &[u8], attrs: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut payload = body.to_vec();
+        for (ty, value) in attrs {
+            payload.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+            payload.extend_from_slice(&ty.to_ne_bytes());
+            payload.extend_from_slice(value);
+            payload.resize((payload.len() + 3) & !3, 0);
+        }
+        let mut message = vec![];
+        message.extend_from_slice(&((16 + payload.len()) as u32).to_ne_bytes());
+        message.extend_from_slice(&ty.to_ne_bytes());
+        message.extend_from_slice(&[0; 10]);
+        message.extend_from_slice(&payload);
+        message
+    }
+
+    #[test]
+    fn parse_messages() {
+        // ifaddrmsg of an ipv6 address on index 7, which has no label
+        let mut ifaddr = [0u8; 8];
+        ifaddr[0] = 10;
+        ifaddr[4..8].copy_from_slice(&7u32.to_ne_bytes());
+        let mut buf = message(20, &ifaddr, &[(1, &[0x20, 0x01, 0x0d, 0xb8])]);
+        // ifinfomsg of a link going down, carrying its name
+        let mut ifinfo = [0u8; 16];
+        ifinfo[4..8].copy_from_slice(&3u32.to_ne_bytes());
+        buf.extend(message(16, &ifinfo, &[(3, b"pppoe-wan\0")]));
+        // a route message is ignored
+        buf.extend(message(24, &[0; 12], &[]));
+
+        let names = parse(&buf, |index| (index == 7).then(|| "br-lan".to_string()));
+        assert_eq!(names, vec!["br-lan", "pppoe-wan"]);
+    }
+}
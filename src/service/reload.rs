@@ -0,0 +1,89 @@
+use crate::service::{dns::TaskManager, store::StoreService};
+use std::{sync::Arc, time::Duration, time::SystemTime};
+use tokio::{fs::metadata, time::interval};
+use tracing::{error, info};
+
+/// how often the modification time of the config file is checked
+const POLL: Duration = Duration::from_secs(5);
+
+/// reloads the config file when it is modified, or on SIGHUP
+pub fn watch_config(store: Arc<StoreService>, manager: Arc<TaskManager>) {
+    tokio::spawn(async move {
+        let path = store.get_config_path().clone();
+        let modified = |path| async move { metadata(path).await?.modified() };
+        let mut last: Option<SystemTime> = modified(&path).await.ok();
+        let mut hangup = hangup();
+        let mut interval = interval(POLL);
+        info!("watch config file {}", path.to_string_lossy());
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let current = modified(&path).await.ok();
+                    if current == last {
+                        continue;
+                    }
+                    last = current;
+                }
+                _ = hangup.recv() => info!("SIGHUP received"),
+            }
+            match reload(&store, &manager).await {
+                Ok(_) => {}
+                Err(e) => error!("reload config refused, keep running the old one: {e:#}"),
+            }
+        }
+    });
+}
+
+/// restarts only the tasks whose config changed
+async fn reload(store: &Arc<StoreService>, manager: &TaskManager) -> anyhow::Result<()> {
+    let changes = store.reload().await?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "config reloaded: {} changed, {} removed",
+        changes.changed.len(),
+        changes.removed.len()
+    );
+    for name in changes.removed {
+        manager.delete_task(&name).await;
+    }
+    for config in changes.changed {
+        manager.reload_task(config, store.clone()).await;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn hangup() -> Hangup {
+    use tokio::signal::unix::{SignalKind, signal};
+    match signal(SignalKind::hangup()) {
+        Ok(signal) => Hangup(Some(signal)),
+        Err(e) => {
+            error!("listen SIGHUP fail: {e}");
+            Hangup(None)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup() -> Hangup {
+    Hangup
+}
+
+#[cfg(unix)]
+struct Hangup(Option<tokio::signal::unix::Signal>);
+
+#[cfg(not(unix))]
+struct Hangup;
+
+impl Hangup {
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.0 {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending().await
+    }
+}
@@ -0,0 +1,171 @@
+use crate::provider::ProviderError;
+use rand::Rng;
+use reqwest::ClientBuilder;
+use std::{io::ErrorKind, time::Duration};
+use tokio::time::{error::Elapsed, sleep};
+use tracing::warn;
+
+/// a single http request to a provider, an address api or a webhook, the retries come on top
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub fn http_client() -> ClientBuilder {
+    ClientBuilder::new().timeout(REQUEST_TIMEOUT)
+}
+
+/// jittered exponential backoff: every delay is drawn from `[d/2, d]`, `d` doubling from `base` up to `max`
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            attempt: 0,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = delay / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// runs `f` up to `attempts` times, as long as it fails with a [transient](is_transient) error
+pub async fn retry<T, F, Fut>(step: &str, attempts: u32, mut f: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    const BASE: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(10);
+
+    let mut backoff = Backoff::new(BASE, MAX);
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                let delay = match e.downcast_ref::<ProviderError>() {
+                    // the provider may ask for any delay, the task must not stall on it
+                    Some(ProviderError::RateLimited {
+                        retry_after: Some(after),
+                        ..
+                    }) => backoff.next_delay().max(*after).min(MAX),
+                    _ => backoff.next_delay(),
+                };
+                warn!("{step} failed ({attempt}/{attempts}), retry in {delay:?}: {e:#}");
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// network failures, timeouts, rate limiting and server side errors
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        if let Some(e) = e.downcast_ref::<ProviderError>() {
+            return e.is_retryable();
+        }
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout()
+                || e.is_connect()
+                || e.status()
+                    .is_some_and(|s| s.as_u16() == 429 || s.is_server_error());
+        }
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NetworkUnreachable
+                    | ErrorKind::HostUnreachable
+            );
+        }
+        e.is::<Elapsed>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(2), Duration::from_secs(10));
+        let delays: Vec<Duration> = (0..5).map(|_| backoff.next_delay()).collect();
+        for (delay, max) in delays.iter().zip([2, 4, 8, 10, 10]) {
+            let max = Duration::from_secs(max);
+            assert!(
+                *delay >= max / 2 && *delay <= max,
+                "{delay:?} not in {max:?}"
+            );
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_transient() {
+        let calls = Cell::new(0);
+        let result = retry("test", 3, || async {
+            calls.set(calls.get() + 1);
+            Err::<(), _>(std::io::Error::from(ErrorKind::ConnectionRefused).into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        let result = retry("test", 3, || async {
+            calls.set(calls.get() + 1);
+            Err::<(), _>(anyhow::anyhow!("cannot find valid ip address"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let result = retry("test", 3, || async {
+            calls.set(calls.get() + 1);
+            Err::<(), _>(ProviderError::Auth("invalid token".into()).into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_after_capped() {
+        let started = tokio::time::Instant::now();
+        let result = retry("test", 2, || async {
+            Err::<(), _>(
+                ProviderError::RateLimited {
+                    retry_after: Some(Duration::from_secs(86400)),
+                    message: "slow down".into(),
+                }
+                .into(),
+            )
+        })
+        .await;
+        assert!(result.is_err());
+        assert!(started.elapsed() <= Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,374 @@
+use crate::model::{DnsConfig, Schedule};
+use anyhow::{Context, bail};
+use rand::Rng;
+use std::{str::FromStr, sync::OnceLock, time::Duration};
+use time::{OffsetDateTime, UtcOffset};
+use tz::TimeZone;
+
+static LOCAL_ZONE: OnceLock<Option<TimeZone>> = OnceLock::new();
+
+/// the rules of the local time zone are read once at startup, the offset is looked up
+/// for every scheduled time so the runs follow daylight saving changes.
+pub fn init_local_zone() {
+    LOCAL_ZONE.get_or_init(|| TimeZone::local().ok());
+}
+
+pub fn now_local() -> OffsetDateTime {
+    to_local(OffsetDateTime::now_utc())
+}
+
+/// `at` in the local offset in effect at that time
+pub fn to_local(at: OffsetDateTime) -> OffsetDateTime {
+    in_zone(LOCAL_ZONE.get().and_then(Option::as_ref), at)
+}
+
+/// `at` is kept as is without a zone
+fn in_zone(zone: Option<&TimeZone>, at: OffsetDateTime) -> OffsetDateTime {
+    let offset = zone
+        .and_then(|zone| zone.find_local_time_type(at.unix_timestamp()).ok())
+        .and_then(|local| UtcOffset::from_whole_seconds(local.ut_offset()).ok());
+    match offset {
+        Some(offset) => at.to_offset(offset),
+        None => at,
+    }
+}
+
+/// when a task runs next: every `interval`, or at `cron` times, outside `quiet` hours, plus `jitter`
+pub struct Scheduler {
+    interval: Duration,
+    cron: Option<Cron>,
+    quiet: Option<Quiet>,
+    jitter: Duration,
+}
+
+impl Scheduler {
+    pub fn new(config: &DnsConfig) -> anyhow::Result<Self> {
+        let mut scheduler = Self::every(config.interval);
+        if let Some(Schedule {
+            cron,
+            quiet,
+            jitter,
+        }) = &config.schedule
+        {
+            scheduler.cron = cron.as_deref().map(Cron::from_str).transpose()?;
+            scheduler.quiet = quiet.as_deref().map(Quiet::from_str).transpose()?;
+            scheduler.jitter = Duration::from_secs(jitter.unwrap_or_default());
+        }
+        Ok(scheduler)
+    }
+
+    pub fn every(interval: u64) -> Self {
+        Self {
+            interval: Duration::from_secs(interval.max(1)),
+            cron: None,
+            quiet: None,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    /// the regular period, a failure backoff never exceeds it
+    pub fn period(&self) -> Duration {
+        self.interval
+    }
+
+    /// the first run after startup, not aligned to `cron` so a stale record is fixed early
+    pub fn first(&self, now: OffsetDateTime) -> OffsetDateTime {
+        self.settle(now + self.random_jitter())
+    }
+
+    pub fn next(&self, now: OffsetDateTime) -> OffsetDateTime {
+        let next = match &self.cron {
+            Some(cron) => cron.next_after(now),
+            None => now + self.interval,
+        };
+        self.settle(next + self.random_jitter())
+    }
+
+    /// moves `at` out of the quiet hours
+    pub fn settle(&self, at: OffsetDateTime) -> OffsetDateTime {
+        let at = to_local(at);
+        match &self.quiet {
+            Some(quiet) if quiet.contains(at) => {
+                let end = quiet.end_after(at);
+                // the first cron time after the quiet hours
+                match &self.cron {
+                    Some(cron) if !cron.matches(end) => cron.next_after(end),
+                    _ => end,
+                }
+            }
+            _ => at,
+        }
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        self.jitter.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+/// `minute hour day-of-month month day-of-week`, with `*`, `a-b`, `*/n`, `a-b/n` and `,` lists
+#[derive(Debug)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// cron matches either day field when both are restricted
+    any_day: bool,
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("cron expression requires 5 fields: {expr}");
+        };
+        let mut weekdays = field(weekday, 0, 7).context("invalid day of week")?;
+        // both 0 and 7 are sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        let cron = Self {
+            minutes: field(minute, 0, 59).context("invalid minute")?,
+            hours: field(hour, 0, 23).context("invalid hour")?,
+            days: field(day, 1, 31).context("invalid day of month")?,
+            months: field(month, 1, 12).context("invalid month")?,
+            weekdays,
+            any_day: day != "*" && weekday != "*",
+        };
+        if !cron.fires() {
+            bail!("cron expression never fires: {expr}");
+        }
+        Ok(cron)
+    }
+}
+
+impl Cron {
+    /// whether a selected day of month exists in a selected month, e.g. not `0 0 31 2 *`
+    fn fires(&self) -> bool {
+        const DAYS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        // the day of week matches on its own when both are restricted
+        if self.any_day {
+            return true;
+        }
+        let first = self.days.trailing_zeros();
+        (1..=12).any(|month| bit(self.months, month) && first <= DAYS[month as usize - 1])
+    }
+
+    pub fn matches(&self, at: OffsetDateTime) -> bool {
+        self.matches_day(at)
+            && bit(self.hours, at.hour() as u32)
+            && bit(self.minutes, at.minute() as u32)
+    }
+
+    fn matches_day(&self, at: OffsetDateTime) -> bool {
+        if !bit(self.months, at.month() as u32) {
+            return false;
+        }
+        let day = bit(self.days, at.day() as u32);
+        let weekday = bit(self.weekdays, at.weekday().number_days_from_sunday() as u32);
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// the first matching minute strictly after `now`
+    pub fn next_after(&self, now: OffsetDateTime) -> OffsetDateTime {
+        const MINUTE: time::Duration = time::Duration::minutes(1);
+        const HOUR: time::Duration = time::Duration::hours(1);
+        const DAY: time::Duration = time::Duration::days(1);
+
+        let mut at = now
+            .replace_second(0)
+            .unwrap()
+            .replace_nanosecond(0)
+            .unwrap()
+            + MINUTE;
+        // a matching day exists within 4 years for any valid expression (e.g. feb 29)
+        let limit = now + time::Duration::days(366 * 4 + 1);
+        while at < limit {
+            // the fields are matched against the local time in effect then
+            at = to_local(at);
+            if !self.matches_day(at) {
+                at = at.replace_time(time::Time::MIDNIGHT) + DAY;
+            } else if !bit(self.hours, at.hour() as u32) {
+                at = at.replace_minute(0).unwrap() + HOUR;
+            } else if !bit(self.minutes, at.minute() as u32) {
+                at += MINUTE;
+            } else {
+                return at;
+            }
+        }
+        // expressions that never fire, e.g. `0 0 31 2 *`, are refused when parsed
+        limit
+    }
+}
+
+fn field(expr: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let mut bits = 0;
+    for part in expr.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (from.parse()?, to.parse()?),
+                // `a/n` means from `a` to the end
+                None if part.contains('/') => (range.parse()?, max),
+                None => (range.parse()?, range.parse()?),
+            },
+        };
+        if step == 0 || from < min || to > max || from > to {
+            bail!("{part} out of range {min}-{max}");
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[inline]
+fn bit(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// `HH:MM-HH:MM` local time, may wrap midnight
+#[derive(Debug)]
+pub struct Quiet {
+    start: u32,
+    end: u32,
+}
+
+impl FromStr for Quiet {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| -> anyhow::Result<u32> {
+            let (hour, minute) = value
+                .trim()
+                .split_once(':')
+                .with_context(|| format!("invalid time: {value}"))?;
+            let (hour, minute) = (hour.parse::<u32>()?, minute.parse::<u32>()?);
+            if hour > 23 || minute > 59 {
+                bail!("invalid time: {value}");
+            }
+            Ok(hour * 60 + minute)
+        };
+        let (start, end) = expr
+            .split_once('-')
+            .with_context(|| format!("quiet hours must be HH:MM-HH:MM: {expr}"))?;
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl Quiet {
+    fn minute_of_day(at: OffsetDateTime) -> u32 {
+        at.hour() as u32 * 60 + at.minute() as u32
+    }
+
+    pub fn contains(&self, at: OffsetDateTime) -> bool {
+        let minute = Self::minute_of_day(at);
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+
+    /// the end of the quiet hours containing `at`
+    pub fn end_after(&self, at: OffsetDateTime) -> OffsetDateTime {
+        let end = time::Time::from_hms((self.end / 60) as u8, (self.end % 60) as u8, 0).unwrap();
+        let end = at.replace_time(end);
+        if end <= at {
+            end + time::Duration::days(1)
+        } else {
+            end
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn cron_next() {
+        let cron = Cron::from_str("*/5 * * * *").unwrap();
+        let next = cron.next_after(datetime!(2025-01-01 10:03:20 UTC));
+        assert_eq!(next, datetime!(2025-01-01 10:05 UTC));
+        let next = cron.next_after(datetime!(2025-01-01 10:05 UTC));
+        assert_eq!(next, datetime!(2025-01-01 10:10 UTC));
+
+        let cron = Cron::from_str("30 2 * * 1-5").unwrap();
+        // friday evening -> monday
+        let next = cron.next_after(datetime!(2025-01-03 20:00 UTC));
+        assert_eq!(next, datetime!(2025-01-06 02:30 UTC));
+
+        let cron = Cron::from_str("0 0 29 2 *").unwrap();
+        let next = cron.next_after(datetime!(2025-03-01 00:00 UTC));
+        assert_eq!(next, datetime!(2028-02-29 00:00 UTC));
+
+        // either day field matches when both are restricted
+        let cron = Cron::from_str("0 12 1 * 0").unwrap();
+        let next = cron.next_after(datetime!(2025-01-01 13:00 UTC));
+        assert_eq!(next, datetime!(2025-01-05 12:00 UTC));
+
+        assert!(Cron::from_str("* * * *").is_err());
+        assert!(Cron::from_str("60 * * * *").is_err());
+        assert!(Cron::from_str("*/0 * * * *").is_err());
+        assert!(Cron::from_str("0 0 31 2 *").is_err());
+        assert!(Cron::from_str("0 0 30,31 2,4 *").is_ok());
+        assert!(Cron::from_str("0 0 31 2 1").is_ok());
+    }
+
+    #[test]
+    fn daylight_saving() {
+        let zone = TimeZone::from_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let local = |at| in_zone(Some(&zone), at);
+        assert_eq!(
+            local(datetime!(2025-03-30 00:59 UTC)),
+            datetime!(2025-03-30 01:59 +1)
+        );
+        assert_eq!(
+            local(datetime!(2025-03-30 01:00 UTC)),
+            datetime!(2025-03-30 03:00 +2)
+        );
+        assert_eq!(
+            local(datetime!(2025-10-26 01:00 UTC)),
+            datetime!(2025-10-26 02:00 +1)
+        );
+        let at = datetime!(2025-01-01 10:00 UTC);
+        assert_eq!(in_zone(None, at).offset(), UtcOffset::UTC);
+    }
+
+    #[test]
+    fn quiet_hours() {
+        let quiet = Quiet::from_str("23:00-06:30").unwrap();
+        assert!(quiet.contains(datetime!(2025-01-01 23:30 UTC)));
+        assert!(quiet.contains(datetime!(2025-01-02 03:00 UTC)));
+        assert!(!quiet.contains(datetime!(2025-01-02 06:30 UTC)));
+        assert_eq!(
+            quiet.end_after(datetime!(2025-01-01 23:30 UTC)),
+            datetime!(2025-01-02 06:30 UTC)
+        );
+        assert_eq!(
+            quiet.end_after(datetime!(2025-01-02 01:00 UTC)),
+            datetime!(2025-01-02 06:30 UTC)
+        );
+        assert!(Quiet::from_str("25:00-01:00").is_err());
+    }
+}
@@ -1,29 +1,172 @@
-use crate::model::{DdnsConfig, DnsConfig, Provider, User, Webhook};
-use std::{path::PathBuf, sync::Arc};
+use crate::model::{
+    AddrConfig, DdnsConfig, DnsConfig, DnsState, Family, HistoryEntry, Method, Provider,
+    RuntimeState, User, Webhook,
+    validate::{FieldError, Validate, ValidationError, Validator},
+};
+use anyhow::Context;
+use cipher::Cipher;
+use format::Format;
+use migrate::{Migrated, VERSION};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 use tokio::{
-    fs::{File, create_dir_all, read_to_string, try_exists},
-    io::AsyncWriteExt,
+    fs::{copy, create_dir_all, read_to_string, remove_file, rename, try_exists},
     sync::RwLock,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+type States = BTreeMap<String, RuntimeState>;
+
+mod cipher;
+mod file;
+pub mod format;
+mod migrate;
+#[cfg(test)]
+pub mod testing;
+
+/// history entries kept per config
+const HISTORY: usize = 200;
+/// how long repeated results may stay unwritten
+const STATE_FLUSH: Duration = Duration::from_secs(600);
+
+/// dns configs changed by a reload
+#[derive(Default)]
+pub struct Changes {
+    /// added or modified
+    pub changed: Vec<DnsConfig>,
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    fn new(old: &[DnsConfig], new: &[DnsConfig]) -> anyhow::Result<Self> {
+        let mut changes = Changes::default();
+        for config in new {
+            let stored = old.iter().find(|c| c.name == config.name);
+            // the state is not serialized, so only the user configuration is compared
+            let same = match stored {
+                Some(stored) => serde_json::to_value(stored)? == serde_json::to_value(config)?,
+                None => false,
+            };
+            if !same {
+                changes.changed.push(config.clone());
+            }
+        }
+        changes.removed = old
+            .iter()
+            .filter(|c| new.iter().all(|n| n.name != c.name))
+            .map(|c| c.name.clone())
+            .collect();
+        Ok(changes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// how the store is set up, besides the config path
+#[derive(Default)]
+pub struct StoreOptions {
+    /// where the state file is kept, next to the config by default
+    pub data_dir: Option<PathBuf>,
+    /// the config content in yaml or json, the config path is not read
+    pub content: Option<String>,
+    /// changes are refused and the config is never written
+    pub read_only: bool,
+}
 
 pub struct StoreService {
     file: PathBuf,
     config: Arc<RwLock<DdnsConfig>>,
+    /// written when the history changes, so the config file only changes on user edits
+    state_file: PathBuf,
+    states: Arc<RwLock<States>>,
+    /// repeated results are only in memory, see [`Self::persist_states`]
+    dirty: AtomicBool,
+    /// secrets are written encrypted when the key file exists
+    cipher: Option<Cipher>,
+    read_only: bool,
+    /// the config was given as content, there is no file to reload
+    inline: bool,
 }
 
 impl StoreService {
-    pub async fn new(path: PathBuf) -> anyhow::Result<Self> {
-        let exist = try_exists(&path).await?;
-        if exist {
-            info!("load config from {}", path.to_string_lossy());
-            let config = read_to_string(&path).await?;
-            let config = serde_json::from_str(&config)?;
+    pub async fn new(path: PathBuf, options: StoreOptions) -> anyhow::Result<Self> {
+        let StoreOptions {
+            data_dir,
+            content,
+            read_only,
+        } = options;
+        let inline = content.is_some();
+        let read_only = read_only || inline;
+        let state_file = data_file(&path, data_dir.as_deref(), "state");
+        if let Some(dir) = &data_dir {
+            create_dir_all(dir).await?;
+        }
+        let key_file = path.with_extension("key");
+        let cipher = Cipher::load(&key_file).await?;
+        let loaded = match content {
+            Some(content) => {
+                info!("load config from DDNS_RS_CONFIG_DATA");
+                Some(migrate::parse(Format::Yaml, &content)?)
+            }
+            None if try_exists(&path).await? => {
+                info!("load config from {}", path.to_string_lossy());
+                Some(load(&path, read_only).await?)
+            }
+            None => None,
+        };
+        if let Some(Migrated {
+            mut config,
+            from,
+            states: legacy,
+        }) = loaded
+        {
+            warn_invalid(&config);
+            let plain = open_secrets(&mut config, cipher.as_ref(), &key_file)?;
+            let mut states = load_states(&state_file).await;
+            if !legacy.is_empty() {
+                merge_states(&mut states, legacy);
+                flush_states(&state_file, &states).await?;
+            }
+            // a read-only config is only upgraded in memory
+            if !read_only && from < VERSION {
+                info!("migrate config from version {from} to {VERSION}");
+                // the rotated backups are overwritten by later saves, this one is kept
+                copy(&path, file::with_suffix(&path, &format!("v{from}.bak"))).await?;
+                flush(&path, &config, cipher.as_ref()).await?;
+                if plain && let Some(cipher) = &cipher {
+                    seal_backups(&path, cipher).await?;
+                }
+            } else if !read_only
+                && plain
+                && let Some(cipher) = &cipher
+            {
+                info!("encrypt secrets with {}", key_file.to_string_lossy());
+                seal(&path, &config, cipher).await?;
+            }
             return Ok(Self {
                 file: path,
                 config: Arc::new(RwLock::new(config)),
+                state_file,
+                states: Arc::new(RwLock::new(states)),
+                dirty: AtomicBool::new(false),
+                cipher,
+                read_only,
+                inline,
             });
         }
+        if read_only {
+            anyhow::bail!("config {} does not exist", path.to_string_lossy());
+        }
         info!("create initial config at {}", path.to_string_lossy());
         let Some(parent) = path.parent() else {
             error!("parent path does not exist");
@@ -33,23 +176,82 @@ impl StoreService {
             create_dir_all(parent).await?;
         }
         let config = DdnsConfig {
+            version: VERSION,
             listen: "0.0.0.0:6789".into(),
             user: None,
             ddns: vec![],
             provider: vec![],
             webhook: vec![],
         };
-        let mut file = File::options().create(true).write(true).open(&path).await?;
-        let value = serde_json::to_string_pretty(&config)?;
-        file.write_all(value.as_bytes()).await?;
-        file.flush().await?;
+        flush(&path, &config, cipher.as_ref()).await?;
         info!("initial config write complete");
         Ok(Self {
             file: path,
             config: Arc::new(RwLock::new(config)),
+            state_file,
+            states: Default::default(),
+            dirty: AtomicBool::new(false),
+            cipher,
+            read_only,
+            inline,
         })
     }
 
+    /// changes are refused in read-only mode
+    pub fn writable(&self) -> anyhow::Result<()> {
+        if self.read_only {
+            anyhow::bail!("config is read-only");
+        }
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// whether the config is read from a file that can be reloaded
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+
+    /// re-reads the config file edited by hand, the running config is kept if it is invalid
+    pub async fn reload(&self) -> anyhow::Result<Changes> {
+        // taken before reading, a save landing meanwhile would be undone otherwise
+        let mut guard = self.config.write().await;
+        let content = read_to_string(&self.file).await?;
+        let mut config = migrate::parse(Format::from_path(&self.file), &content)?.config;
+        config.validate()?;
+        let key_file = self.file.with_extension("key");
+        // secrets added by hand are not left in plain text until the next save
+        if open_secrets(&mut config, self.cipher.as_ref(), &key_file)?
+            && !self.read_only
+            && let Some(cipher) = &self.cipher
+        {
+            info!("encrypt secrets with {}", key_file.to_string_lossy());
+            seal(&self.file, &config, cipher).await?;
+        }
+        // the user initialized in read-only mode is only in memory
+        if self.read_only && config.user.is_none() {
+            config.user = guard.user.clone();
+        }
+        if guard.listen != config.listen {
+            warn!("listen address changed, restart to apply it");
+        }
+        let changes = Changes::new(&guard.ddns, &config.ddns)?;
+        *guard = config;
+        let mut states = self.states.write().await;
+        let before = states.len();
+        states.retain(|name, _| !changes.removed.contains(name));
+        if states.len() != before {
+            flush_states(&self.state_file, &states).await?;
+        }
+        Ok(changes)
+    }
+
+    async fn flush(&self, config: &DdnsConfig) -> anyhow::Result<()> {
+        flush(&self.file, config, self.cipher.as_ref()).await
+    }
+
     pub async fn get_listen(&self) -> String {
         self.config.read().await.listen.clone()
     }
@@ -58,16 +260,30 @@ impl StoreService {
         &self.file
     }
 
+    /// where the running server leaves its [`LocalToken`](crate::service::token::LocalToken)
+    pub fn token_file(&self) -> PathBuf {
+        self.state_file.with_extension("token")
+    }
+
     pub async fn get_user(&self) -> Option<User> {
         self.config.read().await.user.clone()
     }
 
+    /// a read-only config keeps the user in memory, so the system can still be initialized
     pub async fn save_user(&self, user: User) -> anyhow::Result<()> {
         let mut config = self.config.write().await;
+        // only the first user can be set up in memory, a changed password would be lost silently
+        if config.user.is_some() {
+            self.writable()?;
+        }
         let password = bcrypt::hash(user.password.as_bytes(), 4)?;
         let user = User { password, ..user };
         config.user = Some(user);
-        flush(&self.file, &config).await?;
+        if self.read_only {
+            warn!("config is read-only, the user is lost on restart");
+            return Ok(());
+        }
+        self.flush(&config).await?;
         Ok(())
     }
 
@@ -81,17 +297,118 @@ impl StoreService {
     }
 
     pub async fn save_dns_config(&self, config: &DnsConfig) -> anyhow::Result<()> {
+        self.writable()?;
         let mut guard = self.config.write().await;
         guard.ddns.retain(|c| c.name != config.name);
         guard.ddns.push(config.clone());
-        flush(&self.file, &guard).await?;
+        self.flush(&guard).await?;
         Ok(())
     }
 
+    /// checks a config before it is saved, including its references and the `interfaces` of the host.
+    /// interfaces may come up after ddns-rs at boot, so they are only checked here
+    pub async fn check_dns_config(
+        &self,
+        config: &DnsConfig,
+        interfaces: &[(String, IpAddr)],
+    ) -> Result<(), ValidationError> {
+        let mut v = Validator::default();
+        config.check(&mut v);
+        if self.get_dns_provider(&config.provider).await.is_none() {
+            v.error(
+                "provider",
+                format!("provider not found: {}", &config.provider),
+            );
+        }
+        if let Some(webhook) = &config.webhook
+            && self.get_webhook(webhook).await.is_none()
+        {
+            v.error("webhook", format!("webhook not found: {webhook}"));
+        }
+        for (cfg, family) in [(&config.ipv4, Family::Ipv4), (&config.ipv6, Family::Ipv6)] {
+            if let Some(AddrConfig {
+                enabled: true,
+                method: Method::Nic { interface },
+                ..
+            }) = cfg
+                && !interfaces.iter().any(|(name, _)| name == interface)
+            {
+                v.scope(family.to_string(), |v| {
+                    v.error("interface", format!("interface not found: {interface}"))
+                });
+            }
+        }
+        v.finish()
+    }
+
+    pub async fn get_dns_state(&self, name: &str) -> Option<RuntimeState> {
+        self.states.read().await.get(name).cloned()
+    }
+
+    /// `limit` entries after skipping `offset`, newest first, with the total count
+    pub async fn get_dns_history(
+        &self,
+        name: &str,
+        offset: usize,
+        limit: usize,
+    ) -> (usize, Vec<HistoryEntry>) {
+        let guard = self.states.read().await;
+        let Some(runtime) = guard.get(name) else {
+            return (0, vec![]);
+        };
+        let entries = runtime.history.iter().rev().skip(offset).take(limit);
+        (runtime.history.len(), entries.cloned().collect())
+    }
+
+    pub async fn save_dns_state(
+        &self,
+        name: &str,
+        family: Family,
+        state: &DnsState,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.states.write().await;
+        let runtime = guard.entry(name.to_string()).or_default();
+        if !record(runtime, family, state, duration) {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.dirty.store(false, Ordering::Relaxed);
+        flush_states(&self.state_file, &guard).await?;
+        Ok(())
+    }
+
+    /// writes the states if repeated results left them unwritten
+    pub async fn persist_states(&self) -> anyhow::Result<()> {
+        let guard = self.states.read().await;
+        if self.dirty.swap(false, Ordering::Relaxed) {
+            flush_states(&self.state_file, &guard).await?;
+        }
+        Ok(())
+    }
+
+    /// persists the states every [`STATE_FLUSH`], repeated results don't wear the disk on every tick
+    pub fn start_persist_states(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATE_FLUSH);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.persist_states().await {
+                    error!("save dns states fail: {e:#}");
+                }
+            }
+        });
+    }
+
     pub async fn delete_dns_config(&self, name: &str) -> anyhow::Result<()> {
+        self.writable()?;
         let mut guard = self.config.write().await;
         guard.ddns.retain(|c| c.name != name);
-        flush(&self.file, &guard).await?;
+        self.flush(&guard).await?;
+        let mut states = self.states.write().await;
+        if states.remove(name).is_some() {
+            flush_states(&self.state_file, &states).await?;
+        }
         Ok(())
     }
 
@@ -101,17 +418,19 @@ impl StoreService {
     }
 
     pub async fn save_webhook(&self, webhook: &Webhook) -> anyhow::Result<()> {
+        self.writable()?;
         let mut guard = self.config.write().await;
         guard.webhook.retain(|c| c.name != webhook.name);
         guard.webhook.push(webhook.clone());
-        flush(&self.file, &guard).await?;
+        self.flush(&guard).await?;
         Ok(())
     }
 
     pub async fn delete_webhook(&self, name: &str) -> anyhow::Result<()> {
+        self.writable()?;
         let mut guard = self.config.write().await;
         guard.webhook.retain(|c| c.name != name);
-        flush(&self.file, &guard).await?;
+        self.flush(&guard).await?;
         Ok(())
     }
 
@@ -129,21 +448,703 @@ impl StoreService {
     }
 
     pub async fn save_dns_provider(&self, provider: &Provider) -> anyhow::Result<()> {
+        self.writable()?;
         let mut config = self.config.write().await;
         config.provider.retain(|p| p.name != provider.name);
         config.provider.push(provider.clone());
-        flush(&self.file, &config).await?;
+        self.flush(&config).await?;
         Ok(())
     }
     pub async fn delete_dns_provider(&self, name: &str) -> anyhow::Result<()> {
+        self.writable()?;
         let mut config = self.config.write().await;
         config.provider.retain(|p| p.name != name);
-        flush(&self.file, &config).await?;
+        self.flush(&config).await?;
         Ok(())
     }
 }
 
-async fn flush(path: &PathBuf, config: &DdnsConfig) -> anyhow::Result<()> {
-    tokio::fs::write(path, serde_json::to_string_pretty(config)?).await?;
+/// whether the result was added to the history, repeated results only refresh the state
+fn record(
+    runtime: &mut RuntimeState,
+    family: Family,
+    state: &DnsState,
+    duration: Duration,
+) -> bool {
+    let (addr, error, message) = match state {
+        DnsState::Succeed { addr, .. } => {
+            runtime.succeeded += 1;
+            (Some(*addr), None, None)
+        }
+        DnsState::Failed { message, error, .. } => {
+            runtime.failed += 1;
+            (None, *error, Some(message.clone()))
+        }
+    };
+    let mut entries = runtime.history.iter().rev().filter(|e| e.family == family);
+    let last = entries.next();
+    if last.is_some_and(|last| last.addr == addr && last.error == error) {
+        runtime.set(family, state.clone());
+        return false;
+    }
+    let old = match runtime.get(family) {
+        Some(DnsState::Succeed { addr, .. }) => Some(*addr),
+        _ => last.into_iter().chain(entries).find_map(|entry| entry.addr),
+    };
+    let timestamp = match state {
+        DnsState::Succeed { timestamp, .. } | DnsState::Failed { timestamp, .. } => *timestamp,
+    };
+    if runtime.history.len() >= HISTORY {
+        runtime.history.pop_front();
+    }
+    runtime.history.push_back(HistoryEntry {
+        timestamp,
+        family,
+        old,
+        addr,
+        duration: duration.as_millis() as _,
+        error,
+        message,
+    });
+    runtime.set(family, state.clone());
+    true
+}
+
+/// an unreadable state file only loses the last results
+async fn load_states(path: &Path) -> States {
+    let content = match read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return States::new(),
+        Err(e) => {
+            warn!("read state file {} fail: {e}", path.to_string_lossy());
+            return States::new();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("parse state file {} fail: {e}", path.to_string_lossy());
+        States::new()
+    })
+}
+
+/// states kept in the config by older versions, the state file wins
+fn merge_states(states: &mut States, legacy: States) {
+    for (name, legacy) in legacy {
+        let runtime = states.entry(name).or_default();
+        runtime.ipv4 = runtime.ipv4.take().or(legacy.ipv4);
+        runtime.ipv6 = runtime.ipv6.take().or(legacy.ipv6);
+    }
+}
+
+/// configs saved before a check was added still run, so they only get warnings
+fn warn_invalid(config: &DdnsConfig) {
+    if let Err(ValidationError(errors)) = config.validate() {
+        for FieldError { field, message } in errors {
+            warn!("invalid config {field}: {message}");
+        }
+    }
+}
+
+/// the config at `path`, or the newest backup that parses if it is unreadable.
+/// a config that parses but fails validation is never replaced
+async fn load(path: &Path, read_only: bool) -> anyhow::Result<Migrated> {
+    let format = Format::from_path(path);
+    let error = match read_to_string(path).await {
+        Ok(content) => match migrate::parse(format, &content) {
+            Ok(config) => return Ok(config),
+            Err(e) => e,
+        },
+        Err(e) => e.into(),
+    };
+    // restoring a backup writes the config
+    let backups = if read_only {
+        vec![]
+    } else {
+        file::backups(path)
+    };
+    for backup in backups {
+        let Ok(content) = read_to_string(&backup).await else {
+            continue;
+        };
+        match migrate::parse(format, &content) {
+            Ok(config) => {
+                warn!(
+                    "config {} is invalid ({error:#}), fall back to {}",
+                    path.to_string_lossy(),
+                    backup.to_string_lossy()
+                );
+                // kept for inspection, the next backup would overwrite a good one otherwise
+                rename(path, file::with_suffix(path, "corrupt")).await?;
+                file::write_atomic(path, content.as_bytes()).await?;
+                return Ok(config);
+            }
+            Err(e) => warn!("backup {} is invalid: {e:#}", backup.to_string_lossy()),
+        }
+    }
+    Err(error.context(format!("load config {}", path.to_string_lossy())))
+}
+
+/// a file kept along the config, in `data_dir` if given, e.g. `ddns-rs.state`
+pub fn data_file(config: &Path, data_dir: Option<&Path>, extension: &str) -> PathBuf {
+    match (data_dir, config.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name).with_extension(extension),
+        _ => config.with_extension(extension),
+    }
+}
+
+/// creates the key file of the config, its secrets are encrypted when it is loaded next
+pub async fn create_key(config: &Path) -> anyhow::Result<PathBuf> {
+    let path = config.with_extension("key");
+    Cipher::create(&path).await?;
+    Ok(path)
+}
+
+/// rewrites the config at `input` in the format of `output`, e.g. `ddns-rs.conf` to `ddns-rs.toml`
+pub async fn convert(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let content = read_to_string(input)
+        .await
+        .with_context(|| format!("read config {}", input.to_string_lossy()))?;
+    let Migrated {
+        config,
+        states: legacy,
+        ..
+    } = migrate::parse(Format::from_path(input), &content)
+        .with_context(|| format!("parse config {}", input.to_string_lossy()))?;
+    // the states are not written to the config, so they are moved along
+    let (from, to) = (
+        input.with_extension("state"),
+        output.with_extension("state"),
+    );
+    let mut states = load_states(&from).await;
+    let moved = !legacy.is_empty();
+    merge_states(&mut states, legacy);
+    if moved || (from != to && !states.is_empty()) {
+        flush_states(&to, &states).await?;
+    }
+    // the secrets are kept as they are, the key file has to be copied along
+    flush(output, &config, None).await
+}
+
+async fn flush_states(path: &Path, states: &States) -> anyhow::Result<()> {
+    file::write_atomic(path, serde_json::to_string_pretty(states)?.as_bytes()).await
+}
+
+/// decrypts the secrets read from the file, whether some are still plain and should be encrypted
+fn open_secrets(
+    config: &mut DdnsConfig,
+    cipher: Option<&Cipher>,
+    key_file: &Path,
+) -> anyhow::Result<bool> {
+    match cipher {
+        Some(cipher) => cipher.open(config),
+        None => cipher::check_encrypted(config, key_file).map(|_| false),
+    }
+}
+
+async fn flush(path: &Path, config: &DdnsConfig, cipher: Option<&Cipher>) -> anyhow::Result<()> {
+    let content = match cipher {
+        Some(cipher) => Format::from_path(path).to_string(&cipher.seal(config)?)?,
+        None => Format::from_path(path).to_string(config)?,
+    };
+    file::rotate(path).await?;
+    file::write_atomic(path, content.as_bytes()).await
+}
+
+/// rewrites the config with its plain secrets encrypted. the plain file is not rotated
+/// into a backup, and the older backups are sealed too, so no plain secret is left behind
+async fn seal(path: &Path, config: &DdnsConfig, cipher: &Cipher) -> anyhow::Result<()> {
+    let content = Format::from_path(path).to_string(&cipher.seal(config)?)?;
+    file::write_atomic(path, content.as_bytes()).await?;
+    seal_backups(path, cipher).await
+}
+
+/// encrypts the plain secrets of the backups in place, keeping their format and version.
+/// a backup that can't be sealed that way is removed
+async fn seal_backups(path: &Path, cipher: &Cipher) -> anyhow::Result<()> {
+    let format = Format::from_path(path);
+    let versioned = (0..VERSION).map(|from| file::with_suffix(path, &format!("v{from}.bak")));
+    for backup in file::backups(path).into_iter().chain(versioned) {
+        let Ok(content) = read_to_string(&backup).await else {
+            continue;
+        };
+        let Ok(Migrated { mut config, .. }) = migrate::parse(format, &content) else {
+            continue;
+        };
+        let sealed = cipher.seal_text(&content, &mut config)?;
+        if sealed == content {
+            continue;
+        }
+        // a secret also found elsewhere in the file would be replaced there as well
+        let mut same = || -> anyhow::Result<bool> {
+            cipher.open(&mut config)?;
+            let mut parsed = migrate::parse(format, &sealed)?.config;
+            let plain = cipher.open(&mut parsed)?;
+            Ok(!plain && serde_json::to_value(&parsed)? == serde_json::to_value(&config)?)
+        };
+        match same() {
+            Ok(true) => file::write_atomic(&backup, sealed.as_bytes()).await?,
+            _ => {
+                warn!(
+                    "remove backup {}, its plain secrets can't be encrypted",
+                    backup.to_string_lossy()
+                );
+                remove_file(&backup).await?;
+            }
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ErrorKind;
+    use serde_json::json;
+    use testing::{inline_store, sample_config, temp_dir, temp_store};
+
+    #[tokio::test]
+    async fn separate_state() {
+        let mut home = sample_config("cloudflare");
+        home["ipv4"] = json!({
+            "enabled": true,
+            "method": "nic",
+            "interface": "eth0",
+            "state": { "kind": "succeed", "timestamp": 1, "addr": "192.0.2.1" }
+        });
+        let legacy = json!({ "listen": "0.0.0.0:6789", "ddns": [home] });
+
+        // states written by older versions move to the state file
+        let (path, store) = temp_store("store", Some(&legacy)).await;
+        let dir = path.parent().unwrap();
+        let config = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(!config.contains("succeed"));
+        let state = store.get_dns_state("home").await.unwrap();
+        assert!(matches!(
+            state.ipv4,
+            Some(DnsState::Succeed { timestamp: 1, .. })
+        ));
+
+        let failed = DnsState::Failed {
+            timestamp: 2,
+            message: "timeout".to_string(),
+            error: None,
+        };
+        store
+            .save_dns_state("home", Family::Ipv4, &failed, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), config);
+
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        let state = store.get_dns_state("home").await.unwrap();
+        assert!(matches!(
+            state.ipv4,
+            Some(DnsState::Failed { timestamp: 2, .. })
+        ));
+        assert_eq!(state.failed, 1);
+
+        // a repeated result waits for the next persist
+        let state_file = dir.join("ddns-rs.state");
+        let written = tokio::fs::read_to_string(&state_file).await.unwrap();
+        let repeated = DnsState::Failed {
+            timestamp: 3,
+            message: "timeout".to_string(),
+            error: None,
+        };
+        store
+            .save_dns_state("home", Family::Ipv4, &repeated, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(&state_file).await.unwrap(),
+            written
+        );
+        store.persist_states().await.unwrap();
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        let state = store.get_dns_state("home").await.unwrap();
+        assert!(matches!(
+            state.ipv4,
+            Some(DnsState::Failed { timestamp: 3, .. })
+        ));
+        assert_eq!(state.failed, 2);
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[test]
+    fn record_changes() {
+        let mut runtime = RuntimeState::default();
+        let succeed = |timestamp, addr: [u8; 4]| DnsState::Succeed {
+            timestamp,
+            addr: addr.into(),
+        };
+        let duration = Duration::from_millis(20);
+        assert!(record(
+            &mut runtime,
+            Family::Ipv4,
+            &succeed(1, [192, 0, 2, 1]),
+            duration,
+        ));
+        assert!(!record(
+            &mut runtime,
+            Family::Ipv4,
+            &succeed(2, [192, 0, 2, 1]),
+            duration,
+        ));
+        let failed = DnsState::Failed {
+            timestamp: 3,
+            message: "timeout".to_string(),
+            error: Some(ErrorKind::Transient),
+        };
+        assert!(record(&mut runtime, Family::Ipv4, &failed, duration));
+        assert!(record(
+            &mut runtime,
+            Family::Ipv4,
+            &succeed(4, [192, 0, 2, 2]),
+            duration,
+        ));
+
+        assert_eq!(runtime.succeeded, 3);
+        assert_eq!(runtime.failed, 1);
+        let history: Vec<_> = runtime
+            .history
+            .iter()
+            .map(|e| (e.timestamp, e.old, e.addr, e.error))
+            .collect();
+        let addr = |addr: [u8; 4]| Some(IpAddr::from(addr));
+        assert_eq!(
+            history,
+            [
+                (1, None, addr([192, 0, 2, 1]), None),
+                (3, addr([192, 0, 2, 1]), None, Some(ErrorKind::Transient)),
+                (4, addr([192, 0, 2, 1]), addr([192, 0, 2, 2]), None),
+            ]
+        );
+        assert!(matches!(
+            runtime.ipv4,
+            Some(DnsState::Succeed { timestamp: 4, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn fall_back_to_backup() {
+        let (path, store) = temp_store("backup", None).await;
+        let user = User {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+        };
+        store.save_user(user).await.unwrap();
+        // truncated by a power cut
+        tokio::fs::write(&path, "{\"listen\": \"0.0").await.unwrap();
+
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(store.get_listen().await, "0.0.0.0:6789");
+        assert!(store.get_user().await.is_none());
+        let corrupt = file::with_suffix(&path, "corrupt");
+        assert!(try_exists(&corrupt).await.unwrap());
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn keep_invalid_config() {
+        let (path, store) = temp_store("invalid", None).await;
+        let user = User {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+        };
+        // leaves a valid backup behind
+        store.save_user(user).await.unwrap();
+        // edited by hand, accepted before the interval was checked
+        let mut fast = sample_config("cloudflare");
+        fast["interval"] = json!(1);
+        let content = json!({
+            "version": VERSION,
+            "listen": "0.0.0.0:6789",
+            "ddns": [fast],
+        })
+        .to_string();
+        tokio::fs::write(&path, &content).await.unwrap();
+
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        let config = store.get_dns_config("home").await.unwrap();
+        assert_eq!(config.interval, 1);
+        assert!(store.get_user().await.is_none());
+        let corrupt = file::with_suffix(&path, "corrupt");
+        assert!(!try_exists(&corrupt).await.unwrap());
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), content);
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_only_config() {
+        let dir = temp_dir("read-only").await;
+        let path = dir.join("ddns-rs.conf");
+        let data_dir = dir.join("data");
+        let options = || StoreOptions {
+            data_dir: Some(data_dir.clone()),
+            read_only: true,
+            ..Default::default()
+        };
+        // written by version 0, with the state in the config
+        let mut home = sample_config("cloudflare");
+        home["ipv4"] = json!({
+            "enabled": true,
+            "method": "nic",
+            "interface": "eth0",
+            "state": { "kind": "succeed", "timestamp": 1, "addr": "192.0.2.1" }
+        });
+        let legacy = json!({ "listen": "0.0.0.0:6789", "ddns": [home] }).to_string();
+        tokio::fs::write(&path, &legacy).await.unwrap();
+
+        // the migration is kept in memory
+        let store = StoreService::new(path.clone(), options()).await.unwrap();
+        assert!(store.is_read_only());
+        assert!(!store.is_inline());
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), legacy);
+        assert!(
+            !try_exists(file::with_suffix(&path, "v0.bak"))
+                .await
+                .unwrap()
+        );
+        let state = store.get_dns_state("home").await.unwrap();
+        assert!(state.ipv4.is_some());
+        assert!(try_exists(data_dir.join("ddns-rs.state")).await.unwrap());
+        assert!(!try_exists(path.with_extension("state")).await.unwrap());
+
+        // changes are refused, the first user is only kept in memory
+        let config = store.get_dns_config("home").await.unwrap();
+        assert!(store.save_dns_config(&config).await.is_err());
+        assert!(store.delete_dns_config("home").await.is_err());
+        let webhook = Webhook {
+            name: "hook".to_string(),
+            value: "http://127.0.0.1/".to_string(),
+        };
+        assert!(store.save_webhook(&webhook).await.is_err());
+        let user = User {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+        };
+        store.save_user(user.clone()).await.unwrap();
+        let saved = store.get_user().await.unwrap();
+        store.reload().await.unwrap();
+        assert!(store.get_user().await.is_some());
+        // a changed password would be lost on restart
+        let changed = User {
+            password: "changed".to_string(),
+            ..user
+        };
+        assert!(store.save_user(changed).await.is_err());
+        assert_eq!(store.get_user().await.unwrap().password, saved.password);
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), legacy);
+
+        // a broken config is not replaced by a backup
+        tokio::fs::copy(&path, &file::backups(&path)[0])
+            .await
+            .unwrap();
+        tokio::fs::write(&path, "{\"listen\": \"0.0").await.unwrap();
+        assert!(StoreService::new(path.clone(), options()).await.is_err());
+        assert!(
+            !try_exists(file::with_suffix(&path, "corrupt"))
+                .await
+                .unwrap()
+        );
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        assert_eq!(content, "{\"listen\": \"0.0");
+
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert!(StoreService::new(path.clone(), options()).await.is_err());
+        assert!(!try_exists(&path).await.unwrap());
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn inline_config() {
+        let dir = temp_dir("inline").await;
+        let path = dir.join("ddns-rs.conf");
+        let data_dir = dir.join("data");
+        let content = json!({ "listen": "127.0.0.1:6789", "ddns": [sample_config("cloudflare")] });
+        let options = StoreOptions {
+            data_dir: Some(data_dir.clone()),
+            content: Some(content.to_string()),
+            ..Default::default()
+        };
+        let store = StoreService::new(path.clone(), options).await.unwrap();
+        assert!(store.is_read_only());
+        assert!(store.is_inline());
+        assert_eq!(store.get_listen().await, "127.0.0.1:6789");
+
+        let config = store.get_dns_config("home").await.unwrap();
+        assert!(store.save_dns_config(&config).await.is_err());
+        let user = User {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+        };
+        store.save_user(user).await.unwrap();
+        assert!(store.get_user().await.is_some());
+
+        // only the states are written, to the data dir
+        let succeed = DnsState::Succeed {
+            timestamp: 1,
+            addr: [192, 0, 2, 1].into(),
+        };
+        store
+            .save_dns_state("home", Family::Ipv4, &succeed, Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(try_exists(data_dir.join("ddns-rs.state")).await.unwrap());
+        assert!(!try_exists(&path).await.unwrap());
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn check_interfaces() {
+        let content = "
+listen: 127.0.0.1:6789
+provider:
+  - name: cf
+    kind: cloudflare
+    apiKey: token
+";
+        let store = inline_store("check", content).await;
+        let mut config = sample_config("cf");
+        config["ipv4"] = json!({ "enabled": true, "method": "nic", "interface": "eth1" });
+        let config: DnsConfig = serde_json::from_value(config).unwrap();
+        let interface = |name: &str| (name.to_string(), IpAddr::from([192, 0, 2, 1]));
+
+        let fields = |result: Result<(), ValidationError>| -> Vec<String> {
+            let ValidationError(errors) = result.unwrap_err();
+            errors.into_iter().map(|e| e.field).collect()
+        };
+        let result = store.check_dns_config(&config, &[interface("lo")]).await;
+        assert_eq!(fields(result), ["ipv4.interface"]);
+        // an empty list still rejects the interface
+        let result = store.check_dns_config(&config, &[]).await;
+        assert_eq!(fields(result), ["ipv4.interface"]);
+        let interfaces = [interface("lo"), interface("eth1")];
+        store.check_dns_config(&config, &interfaces).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_encrypts_secrets() {
+        let dir = temp_dir("encrypt").await;
+        let path = dir.join("ddns-rs.conf");
+        let plain_files = || async {
+            let mut files = vec![];
+            let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+            while let Some(entry) = entries.next_entry().await.unwrap() {
+                let content = tokio::fs::read_to_string(entry.path()).await.unwrap();
+                if content.contains("plain-token") {
+                    files.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+            files
+        };
+        // written by version 0 with a plain secret, before keygen
+        let legacy = json!({
+            "listen": "0.0.0.0:6789",
+            "provider": [{ "name": "cf", "kind": "cloudflare", "apiKey": "plain-token" }],
+        });
+        tokio::fs::write(&path, legacy.to_string()).await.unwrap();
+        Cipher::create(&path.with_extension("key")).await.unwrap();
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        assert!(plain_files().await.is_empty());
+        // the original version is kept
+        let v0 = tokio::fs::read_to_string(file::with_suffix(&path, "v0.bak"))
+            .await
+            .unwrap();
+        assert!(v0.contains("enc:") && !v0.contains("version"));
+        let opened = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+        let cf = opened.get_dns_provider("cf").await.unwrap();
+        assert!(serde_json::to_string(&cf).unwrap().contains("plain-token"));
+
+        // edited by hand after keygen
+        let content = json!({
+            "version": VERSION,
+            "listen": "0.0.0.0:6789",
+            "provider": [{ "name": "cf", "kind": "cloudflare", "apiKey": "plain-token" }],
+        });
+        tokio::fs::write(&path, content.to_string()).await.unwrap();
+        store.reload().await.unwrap();
+        let written = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(written.contains("enc:"));
+        assert!(plain_files().await.is_empty());
+        let provider = store.get_dns_provider("cf").await.unwrap();
+        let provider = serde_json::to_string(&provider).unwrap();
+        assert!(provider.contains("plain-token"));
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_changes() {
+        let dir = temp_dir("reload").await;
+        let path = dir.join("ddns-rs.conf");
+        let dns = |name: &str, interval: u64| {
+            let mut config = sample_config("cloudflare");
+            config["name"] = json!(name);
+            config["interval"] = json!(interval);
+            config
+        };
+        let config = |ddns: Vec<serde_json::Value>| {
+            json!({ "listen": "0.0.0.0:6789", "ddns": ddns }).to_string()
+        };
+        tokio::fs::write(&path, config(vec![dns("a", 60), dns("b", 60)]))
+            .await
+            .unwrap();
+        let store = StoreService::new(path.clone(), Default::default())
+            .await
+            .unwrap();
+
+        tokio::fs::write(
+            &path,
+            config(vec![dns("a", 60), dns("b", 300), dns("c", 60)]),
+        )
+        .await
+        .unwrap();
+        let changes = store.reload().await.unwrap();
+        let changed: Vec<_> = changes.changed.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(changed, ["b", "c"]);
+        assert!(changes.removed.is_empty());
+
+        // an invalid file keeps the running config
+        tokio::fs::write(&path, config(vec![dns("a", 60), dns("a", 60)]))
+            .await
+            .unwrap();
+        assert!(store.reload().await.is_err());
+        assert_eq!(store.list_dns_configs().await.len(), 3);
+
+        let succeed = DnsState::Succeed {
+            timestamp: 1,
+            addr: [192, 0, 2, 1].into(),
+        };
+        for name in ["a", "c"] {
+            store
+                .save_dns_state(name, Family::Ipv4, &succeed, Duration::ZERO)
+                .await
+                .unwrap();
+        }
+        tokio::fs::write(&path, config(vec![dns("c", 60)]))
+            .await
+            .unwrap();
+        let changes = store.reload().await.unwrap();
+        assert!(changes.changed.is_empty());
+        assert_eq!(changes.removed, ["a", "b"]);
+        // the states of removed configs go with them
+        assert!(store.get_dns_state("a").await.is_none());
+        assert!(store.get_dns_state("c").await.is_some());
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
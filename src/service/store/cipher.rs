@@ -0,0 +1,174 @@
+use crate::model::{DdnsConfig, Secret};
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{Aead, generic_array::GenericArray},
+};
+use rand::RngCore;
+use std::path::Path;
+use tokio::{
+    fs::{OpenOptions, read_to_string},
+    io::AsyncWriteExt,
+};
+
+const PREFIX: &str = "enc:";
+const NONCE: usize = 12;
+
+/// encrypts the provider secrets written to the config, keeping them plain in memory
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    /// the base64 key in `path`, none if the file does not exist
+    pub async fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        let content = match read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("read key file {}", path.to_string_lossy())),
+        };
+        let key = STANDARD
+            .decode(content.trim())
+            .ok()
+            .filter(|key| key.len() == 32)
+            .with_context(|| {
+                format!(
+                    "key file {} must be 32 bytes in base64",
+                    path.to_string_lossy()
+                )
+            })?;
+        Ok(Some(Self(ChaCha20Poly1305::new(GenericArray::from_slice(
+            &key,
+        )))))
+    }
+
+    /// writes a new key to `path`, only readable by the owner
+    pub async fn create(path: &Path) -> anyhow::Result<()> {
+        let mut key = [0u8; 32];
+        rand::rng().fill_bytes(&mut key);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options
+            .open(path)
+            .await
+            .with_context(|| format!("create key file {}", path.to_string_lossy()))?;
+        file.write_all(STANDARD.encode(key).as_bytes()).await?;
+        file.sync_all().await?;
+        Ok(())
+    }
+
+    fn encrypt(&self, plain: &str) -> anyhow::Result<String> {
+        let mut nonce = [0u8; NONCE];
+        rand::rng().fill_bytes(&mut nonce);
+        let mut data = nonce.to_vec();
+        let encrypted = self
+            .0
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|_| anyhow::anyhow!("encrypt secret"))?;
+        data.extend(encrypted);
+        Ok(format!("{PREFIX}{}", STANDARD.encode(data)))
+    }
+
+    fn decrypt(&self, value: &str) -> anyhow::Result<String> {
+        let data = STANDARD.decode(value).context("invalid encrypted secret")?;
+        if data.len() < NONCE {
+            bail!("invalid encrypted secret");
+        }
+        let (nonce, encrypted) = data.split_at(NONCE);
+        let plain = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| anyhow::anyhow!("decrypt secret, the key file does not match"))?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    /// the config written to the file, with its plain secrets encrypted
+    pub fn seal(&self, config: &DdnsConfig) -> anyhow::Result<DdnsConfig> {
+        let mut config = config.clone();
+        for secret in secrets_mut(&mut config) {
+            if secret.is_plain() && !secret.0.is_empty() {
+                *secret = Secret(self.encrypt(&secret.0)?);
+            }
+        }
+        Ok(config)
+    }
+
+    /// `content` with the plain secrets of its parsed `config` encrypted in place,
+    /// so the rest of the file is kept as it is
+    pub fn seal_text(&self, content: &str, config: &mut DdnsConfig) -> anyhow::Result<String> {
+        let mut content = content.to_string();
+        for secret in secrets_mut(config) {
+            if secret.is_plain() && !secret.0.is_empty() {
+                content = content.replace(&secret.0, &self.encrypt(&secret.0)?);
+            }
+        }
+        Ok(content)
+    }
+
+    /// decrypts the secrets read from the file, whether any was still plain
+    pub fn open(&self, config: &mut DdnsConfig) -> anyhow::Result<bool> {
+        let mut plain = false;
+        for secret in secrets_mut(config) {
+            match secret.0.strip_prefix(PREFIX) {
+                Some(value) => *secret = Secret(self.decrypt(value)?),
+                None => plain |= secret.is_plain() && !secret.0.is_empty(),
+            }
+        }
+        Ok(plain)
+    }
+}
+
+/// encrypted secrets can't be used without the key file
+pub fn check_encrypted(config: &mut DdnsConfig, key_file: &Path) -> anyhow::Result<()> {
+    if secrets_mut(config).any(|secret| secret.0.starts_with(PREFIX)) {
+        bail!(
+            "the config has encrypted secrets, but key file {} does not exist",
+            key_file.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+fn secrets_mut(config: &mut DdnsConfig) -> impl Iterator<Item = &mut Secret> {
+    config
+        .provider
+        .iter_mut()
+        .flat_map(|provider| provider.config.secrets_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let cipher = Cipher(ChaCha20Poly1305::new(GenericArray::from_slice(&[1; 32])));
+        let mut config: DdnsConfig = serde_json::from_value(serde_json::json!({
+            "listen": "0.0.0.0:6789",
+            "provider": [
+                { "name": "cf", "kind": "cloudflare", "apiKey": "token" },
+                { "name": "env", "kind": "cloudflare", "apiKey": "env:CF_TOKEN" },
+            ],
+        }))
+        .unwrap();
+        let sealed = cipher.seal(&config).unwrap();
+        let content = serde_json::to_string(&sealed).unwrap();
+        assert!(!content.contains("token"));
+        // references are written as they are
+        assert!(content.contains("env:CF_TOKEN"));
+
+        let mut opened: DdnsConfig = serde_json::from_str(&content).unwrap();
+        assert!(!cipher.open(&mut opened).unwrap());
+        assert_eq!(
+            serde_json::to_value(&opened).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+        assert!(cipher.open(&mut config).unwrap());
+
+        let mut sealed = sealed;
+        assert!(check_encrypted(&mut sealed, Path::new("ddns-rs.key")).is_err());
+        let other = Cipher(ChaCha20Poly1305::new(GenericArray::from_slice(&[0; 32])));
+        assert!(other.open(&mut sealed).is_err());
+    }
+}
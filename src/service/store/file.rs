@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::{File, copy, rename, try_exists},
+    io::AsyncWriteExt,
+};
+
+/// backups kept of the config file, `ddns-rs.conf.1.bak` being the newest
+const BACKUPS: usize = 3;
+
+/// writes a temporary file next to `path` and renames it, so `path` is never truncated
+pub async fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let tmp = with_suffix(path, "tmp");
+    let mut file = File::create(&tmp).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+    drop(file);
+    rename(&tmp, path).await?;
+    sync_dir(path).await;
+    Ok(())
+}
+
+/// the rename is only durable once the directory is synced
+#[cfg(unix)]
+async fn sync_dir(path: &Path) {
+    if let Some(parent) = path.parent()
+        && let Ok(dir) = File::open(parent).await
+    {
+        let _ = dir.sync_all().await;
+    }
+}
+
+#[cfg(not(unix))]
+async fn sync_dir(_: &Path) {}
+
+/// copies `path` to the newest backup, dropping the oldest one
+pub async fn rotate(path: &Path) -> anyhow::Result<()> {
+    if !try_exists(path).await? {
+        return Ok(());
+    }
+    let backups = backups(path);
+    for i in (1..backups.len()).rev() {
+        if try_exists(&backups[i - 1]).await? {
+            rename(&backups[i - 1], &backups[i]).await?;
+        }
+    }
+    copy(path, &backups[0]).await?;
+    Ok(())
+}
+
+/// newest first
+pub fn backups(path: &Path) -> Vec<PathBuf> {
+    (1..=BACKUPS)
+        .map(|i| with_suffix(path, &format!("{i}.bak")))
+        .collect()
+}
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::testing::temp_dir;
+    use tokio::fs::{read_to_string, remove_dir_all};
+
+    #[tokio::test]
+    async fn rotate_backups() {
+        let dir = temp_dir("file").await;
+        let path = dir.join("ddns-rs.conf");
+        for i in 0..5 {
+            rotate(&path).await.unwrap();
+            write_atomic(&path, i.to_string().as_bytes()).await.unwrap();
+        }
+        assert_eq!(read_to_string(&path).await.unwrap(), "4");
+        let backups = backups(&path);
+        for (backup, expected) in backups.iter().zip(["3", "2", "1"]) {
+            assert_eq!(read_to_string(backup).await.unwrap(), expected);
+        }
+        assert!(!try_exists(with_suffix(&path, "tmp")).await.unwrap());
+        remove_dir_all(dir).await.unwrap();
+    }
+}
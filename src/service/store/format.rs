@@ -0,0 +1,85 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fmt, path::Path};
+
+/// the format of a config file, chosen by its extension
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// `.toml`, `.yaml` and `.yml`, anything else (e.g. `.conf`) is json
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> anyhow::Result<T> {
+        Ok(match self {
+            Self::Json => serde_json::from_str(content)?,
+            Self::Toml => toml::from_str(content)?,
+            Self::Yaml => serde_yaml_ng::from_str(content)?,
+        })
+    }
+
+    pub fn to_string<T: Serialize>(self, value: &T) -> anyhow::Result<String> {
+        Ok(match self {
+            Self::Json => serde_json::to_string_pretty(value)?,
+            Self::Toml => toml::to_string_pretty(value)?,
+            Self::Yaml => serde_yaml_ng::to_string(value)?,
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DdnsConfig;
+
+    #[test]
+    fn round_trip() {
+        let config: DdnsConfig = serde_json::from_value(serde_json::json!({
+            "listen": "0.0.0.0:6789",
+            "ddns": [{
+                "name": "home",
+                "domain": "example.com",
+                "subdomain": "www",
+                "interval": 300,
+                "ipv4": { "enabled": true, "method": "nic", "interface": "eth0" },
+                "ipv6": { "enabled": true, "method": "dns", "server": "1.1.1.1", "query": "example.com" },
+                "provider": "cf",
+                "schedule": { "cron": "*/5 * * * *" },
+                "paused": true
+            }],
+            "provider": [{ "name": "cf", "kind": "cloudflare", "apiKey": "key" }],
+            "webhook": [{ "name": "hook", "value": "curl" }]
+        }))
+        .unwrap();
+        let expected = serde_json::to_value(&config).unwrap();
+        for format in [Format::Json, Format::Toml, Format::Yaml] {
+            let content = format.to_string(&config).unwrap();
+            let parsed: DdnsConfig = format.parse(&content).unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), expected, "{format}");
+        }
+
+        assert_eq!(Format::from_path(Path::new("ddns-rs.conf")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("ddns-rs.TOML")), Format::Toml);
+        assert_eq!(Format::from_path(Path::new("ddns-rs.yml")), Format::Yaml);
+    }
+}
@@ -0,0 +1,131 @@
+use super::{States, format::Format};
+use crate::model::{DdnsConfig, DnsState, RuntimeState};
+use anyhow::{Context, bail};
+use serde_json::{Map, Value};
+
+/// the config version written by this build, bumped by every migration
+pub const VERSION: u64 = 1;
+
+/// a migration upgrades a document by one version, data moved out of the config goes to `states`
+type Migration = fn(&mut Map<String, Value>, &mut States) -> anyhow::Result<()>;
+
+/// `MIGRATIONS[n]` upgrades version `n` to `n + 1`
+const MIGRATIONS: [Migration; VERSION as usize] = [v0_states];
+
+/// a config upgraded to [`VERSION`]
+pub struct Migrated {
+    pub config: DdnsConfig,
+    /// the version of the document before the migration
+    pub from: u64,
+    /// states kept in the document by older versions
+    pub states: States,
+}
+
+pub fn parse(format: Format, content: &str) -> anyhow::Result<Migrated> {
+    let mut document: Value = format.parse(content)?;
+    let Value::Object(map) = &mut document else {
+        bail!("config must be a table");
+    };
+    let from = match map.get("version") {
+        None => 0,
+        Some(version) => version.as_u64().context("version must be a number")?,
+    };
+    if from > VERSION {
+        bail!("config version {from} is newer than {VERSION}, upgrade ddns-rs to load it");
+    }
+    let mut states = States::new();
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(map, &mut states).with_context(|| format!("migrate config version {version}"))?;
+    }
+    map.insert("version".to_string(), VERSION.into());
+    Ok(Migrated {
+        config: serde_json::from_value(document)?,
+        from,
+        states,
+    })
+}
+
+/// version 0 kept the latest result of each family in the config
+fn v0_states(map: &mut Map<String, Value>, states: &mut States) -> anyhow::Result<()> {
+    let Some(Value::Array(configs)) = map.get_mut("ddns") else {
+        return Ok(());
+    };
+    for config in configs {
+        let name = config
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let mut runtime = RuntimeState::default();
+        for (family, state) in [("ipv4", &mut runtime.ipv4), ("ipv6", &mut runtime.ipv6)] {
+            let Some(Value::Object(addr)) = config.get_mut(family) else {
+                continue;
+            };
+            if let Some(value) = addr.remove("state") {
+                *state = serde_json::from_value::<Option<DnsState>>(value)?;
+            }
+        }
+        if runtime.ipv4.is_some() || runtime.ipv6.is_some() {
+            states.insert(name, runtime);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_json(document: Value) -> anyhow::Result<Migrated> {
+        parse(Format::Json, &document.to_string())
+    }
+
+    #[test]
+    fn v0_states_moved() {
+        let migrated = parse_json(json!({
+            "listen": "0.0.0.0:6789",
+            "ddns": [{
+                "name": "test",
+                "domain": "example.com",
+                "subdomain": "www",
+                "interval": 60,
+                "ipv4": {
+                    "enabled": true,
+                    "method": "nic",
+                    "interface": "eth0",
+                    "state": { "kind": "succeed", "timestamp": 1, "addr": "192.0.2.1" }
+                },
+                "ipv6": { "enabled": false, "method": "api", "endpoint": "" },
+                "provider": "cloudflare",
+            }],
+        }))
+        .unwrap();
+        assert_eq!(migrated.from, 0);
+        assert_eq!(migrated.config.version, VERSION);
+        let state = &migrated.states["test"];
+        assert!(matches!(
+            state.ipv4,
+            Some(DnsState::Succeed { timestamp: 1, .. })
+        ));
+        assert!(state.ipv6.is_none());
+        let ipv4 = migrated.config.ddns[0].ipv4.as_ref().unwrap();
+        assert!(ipv4.state.is_none());
+    }
+
+    #[test]
+    fn v0_without_states() {
+        let migrated = parse_json(json!({ "listen": "0.0.0.0:6789" })).unwrap();
+        assert_eq!(migrated.from, 0);
+        assert!(migrated.states.is_empty());
+    }
+
+    #[test]
+    fn current_version() {
+        let migrated = parse_json(json!({ "version": VERSION, "listen": "0.0.0.0:6789" })).unwrap();
+        assert_eq!(migrated.from, VERSION);
+
+        assert!(parse_json(json!({ "version": VERSION + 1, "listen": "0.0.0.0:6789" })).is_err());
+        assert!(parse_json(json!({ "version": "1", "listen": "0.0.0.0:6789" })).is_err());
+    }
+}
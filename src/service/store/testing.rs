@@ -0,0 +1,43 @@
+use super::{StoreOptions, StoreService};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use tokio::fs::{create_dir_all, remove_dir_all, write};
+
+/// an empty directory named after the test, left over files of a killed run are removed
+pub async fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ddns-rs-{name}-{}", std::process::id()));
+    let _ = remove_dir_all(&dir).await;
+    create_dir_all(&dir).await.unwrap();
+    dir
+}
+
+/// `home` updating www.example.com every minute through `provider`
+pub fn sample_config(provider: &str) -> Value {
+    json!({
+        "name": "home",
+        "domain": "example.com",
+        "subdomain": "www",
+        "interval": 60,
+        "provider": provider,
+    })
+}
+
+/// a store in its own temp dir, on `content` if given or on the initial config
+pub async fn temp_store(name: &str, content: Option<&Value>) -> (PathBuf, StoreService) {
+    let path = temp_dir(name).await.join("ddns-rs.conf");
+    if let Some(content) = content {
+        write(&path, content.to_string()).await.unwrap();
+    }
+    let store = StoreService::new(path.clone(), Default::default());
+    (path, store.await.unwrap())
+}
+
+/// a read-only store on `content` in yaml or json, only the states go to its temp dir
+pub async fn inline_store(name: &str, content: &str) -> StoreService {
+    let options = StoreOptions {
+        content: Some(content.to_string()),
+        ..Default::default()
+    };
+    let path = temp_dir(name).await.join("ddns-rs.conf");
+    StoreService::new(path, options).await.unwrap()
+}
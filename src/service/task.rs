@@ -1,76 +1,325 @@
 use crate::{
     model::{
-        AddrConfig, DnsConfig, DnsState, Domain, Family,
+        AddrConfig, DnsConfig, DnsState, Domain, ErrorKind, Family,
         Family::{Ipv4, Ipv6},
         Method, Provider,
     },
-    provider::update_ddns_record,
-    service::{store::StoreService, webhook},
+    provider::{Change, ProviderError, plan_ddns_record, update_ddns_record},
+    service::{
+        detect::DetectCache,
+        dns::{RunState, TaskStatus},
+        retry::{Backoff, retry},
+        schedule::{Scheduler, now_local},
+        store::StoreService,
+        webhook,
+    },
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::{net::IpAddr, str::FromStr, sync::Arc, time::Duration};
 use time::UtcDateTime;
-use tokio::{process::Command, task::JoinHandle, time::interval};
+use tokio::{
+    sync::{
+        Mutex,
+        broadcast::{Receiver, error::RecvError},
+        mpsc,
+    },
+    time::{sleep, timeout},
+};
 use tracing::{debug, error, info, instrument};
 
-pub async fn spawn_ddns_updating_task(
-    config: DnsConfig,
-    store: Arc<StoreService>,
-) -> JoinHandle<()> {
-    tokio::spawn(task(config, store))
+/// interfaces usually flap several times while reconnecting
+const DEBOUNCE: Duration = Duration::from_secs(3);
+const DETECT_TIMEOUT: Duration = Duration::from_secs(30);
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(60);
+const ATTEMPTS: u32 = 3;
+/// first delay before running again after a failure, doubled up to the regular period
+const FAILURE_RETRY: Duration = Duration::from_secs(30);
+
+/// sent to a running task through its channel
+pub enum Command {
+    /// update immediately, ignoring the schedule
+    RunNow,
+    /// replace the config and update immediately
+    Reload(Box<DnsConfig>),
+    Stop,
 }
+
 #[instrument("", skip_all, fields(config = %config.name))]
-async fn task(mut config: DnsConfig, store: Arc<StoreService>) {
-    let duration = Duration::from_secs(config.interval);
-    let mut interval = interval(duration);
-    info!("start ddns updating: interval={duration:?}",);
-    loop {
-        interval.tick().await;
-        let DnsConfig {
-            domain,
-            ipv4,
-            ipv6,
-            provider,
-            ..
-        } = &mut config;
-        let provider = store.get_dns_provider(provider).await;
-        let mut configs = Vec::with_capacity(2);
-        if let Some(ipv4) = ipv4
-            && ipv4.enabled
-        {
-            configs.push((ipv4, Ipv4))
+pub async fn run_ddns_updating_task(
+    mut config: DnsConfig,
+    store: Arc<StoreService>,
+    detect: Arc<DetectCache>,
+    mut events: Receiver<String>,
+    commands: Arc<Mutex<mpsc::Receiver<Command>>>,
+    status: Arc<TaskStatus>,
+    mut immediate: bool,
+) {
+    // kept by the supervisor across restarts
+    let mut commands = commands.lock().await;
+    'reload: loop {
+        // the last results, used by the webhook variables
+        if let Some(runtime) = store.get_dns_state(&config.name).await {
+            for (cfg, family) in [(&mut config.ipv4, Ipv4), (&mut config.ipv6, Ipv6)] {
+                if let Some(cfg) = cfg {
+                    cfg.state = runtime.get(family).cloned();
+                }
+            }
         }
-        if let Some(ipv6) = ipv6
-            && ipv6.enabled
-        {
-            configs.push((ipv6, Ipv6))
+        // idle tasks stay registered until they are reloaded
+        let active = !config.paused
+            && [&config.ipv4, &config.ipv6]
+                .into_iter()
+                .flatten()
+                .any(|cfg| cfg.enabled);
+        if config.paused {
+            info!("config paused, waiting to be resumed");
+        } else if !active {
+            info!("config no valid ipv4/ipv6 definition. task idle.");
         }
-        if configs.is_empty() {
-            info!("config no valid ipv4/ipv6 definition. task terminated.",);
-            return;
+        let scheduler = Scheduler::new(&config).unwrap_or_else(|e| {
+            error!("invalid schedule, fall back to interval: {e:#}");
+            Scheduler::every(config.interval)
+        });
+        let interfaces = match active {
+            true => watched_interfaces(&config),
+            false => vec![],
+        };
+        let mut backoff = Backoff::new(FAILURE_RETRY, scheduler.period().max(FAILURE_RETRY));
+        if active {
+            info!("start ddns updating: interval={:?}", scheduler.period());
         }
-        update(domain, provider, &mut configs).await;
-        match store.save_dns_config(&config).await {
-            Ok(_) => debug!("save dns config state success"),
-            Err(e) => error!("save dns config state fail: {e:#}"),
+        // a manual run skips jitter and quiet hours
+        let mut next = match immediate {
+            true => now_local(),
+            false => scheduler.first(now_local()),
         };
-        notify(&config, &store).await;
+        // addresses probed before the last interface change are stale
+        let mut changed_at = None;
+        loop {
+            status.update(|state| {
+                state.state = RunState::Idle;
+                state.next_run = active.then(|| next.unix_timestamp() as _);
+            });
+            let delay = (next - now_local()).try_into().unwrap_or_default();
+            tokio::select! {
+                _ = sleep(delay), if active => {}
+                name = changed(&mut events, &interfaces) => {
+                    info!("interface {name} changed, updating immediately");
+                    changed_at = Some(Instant::now());
+                    sleep(DEBOUNCE).await;
+                    while events.try_recv().is_ok() {}
+                }
+                command = commands.recv() => match command {
+                    Some(Command::RunNow) if active => info!("run now"),
+                    Some(Command::RunNow) => continue,
+                    Some(Command::Reload(reloaded)) => {
+                        info!("config reloaded");
+                        config = *reloaded;
+                        immediate = true;
+                        continue 'reload;
+                    }
+                    Some(Command::Stop) | None => {
+                        info!("task stopped");
+                        return;
+                    }
+                }
+            }
+            status.update(|state| state.state = RunState::Running);
+            let updated = run_once(&mut config, &store, &detect, changed_at).await;
+            let error = updated.iter().rev().find_map(|(_, state, _)| match state {
+                DnsState::Failed { message, .. } => Some(message.clone()),
+                DnsState::Succeed { .. } => None,
+            });
+            let failed = error.is_some();
+            status.update(|state| {
+                state.runs += 1;
+                if error.is_some() {
+                    state.last_error = error;
+                }
+            });
+            let now = now_local();
+            next = scheduler.next(now);
+            if failed {
+                let retry = scheduler.settle(now + backoff.next_delay());
+                if retry < next {
+                    info!("update failed, retry at {retry}");
+                    next = retry;
+                }
+            } else {
+                backoff.reset();
+            }
+            notify(&config, &store).await;
+        }
     }
 }
 
+/// detects and updates the enabled families once and saves their states,
+/// the webhook is not notified
+pub async fn run_once(
+    config: &mut DnsConfig,
+    store: &StoreService,
+    detect: &DetectCache,
+    since: Option<Instant>,
+) -> Vec<(Family, DnsState, Duration)> {
+    let DnsConfig {
+        name,
+        domain,
+        ipv4,
+        ipv6,
+        provider,
+        ..
+    } = config;
+    let provider = store.get_dns_provider(provider).await;
+    let mut configs = Vec::with_capacity(2);
+    if let Some(ipv4) = ipv4
+        && ipv4.enabled
+    {
+        configs.push((ipv4, Ipv4))
+    }
+    if let Some(ipv6) = ipv6
+        && ipv6.enabled
+    {
+        configs.push((ipv6, Ipv6))
+    }
+    let updated = update(domain, provider, &mut configs, detect, since).await;
+    let mut states = Vec::with_capacity(updated.len());
+    // the config may have been edited meanwhile, only the state is written
+    for (cfg, family) in &configs {
+        let Some((_, duration)) = updated.iter().find(|(updated, _)| updated == family) else {
+            continue;
+        };
+        let Some(state) = &cfg.state else {
+            continue;
+        };
+        match store.save_dns_state(name, *family, state, *duration).await {
+            Ok(_) => debug!("save dns state success"),
+            Err(e) => error!("save dns state fail: {e:#}"),
+        };
+        states.push((*family, state.clone(), *duration));
+    }
+    states
+}
+
+/// what updating a family would change, see [`dry_run`]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize)]
+pub struct Planned {
+    pub family: Family,
+    pub addr: Option<IpAddr>,
+    #[serde(default)]
+    pub changes: Vec<Change>,
+    pub error: Option<ErrorKind>,
+    pub message: Option<String>,
+}
+
+/// detects the enabled families and asks the provider what it would change,
+/// no record is written and no state is saved
+pub async fn dry_run(
+    config: &DnsConfig,
+    store: &StoreService,
+    detect: &DetectCache,
+) -> anyhow::Result<Vec<Planned>> {
+    let provider = store
+        .get_dns_provider(&config.provider)
+        .await
+        .with_context(|| format!("provider not found: {}", config.provider))?;
+    let mut planned = Vec::with_capacity(2);
+    for (cfg, family) in [(&config.ipv4, Ipv4), (&config.ipv6, Ipv6)] {
+        let Some(cfg) = cfg.as_ref().filter(|cfg| cfg.enabled) else {
+            continue;
+        };
+        let mut plan = Planned {
+            family,
+            addr: None,
+            changes: vec![],
+            error: None,
+            message: None,
+        };
+        let addr = timeout(DETECT_TIMEOUT, detect.find_addr(cfg, family, None))
+            .await
+            .context("detect address timed out")
+            .and_then(|result| result);
+        match addr {
+            Ok(Some(addr)) => plan.addr = Some(addr),
+            Ok(None) => plan.message = Some("cannot find valid ip address".to_string()),
+            Err(e) => plan.message = Some(format!("{e:#}")),
+        }
+        let Some(addr) = plan.addr else {
+            plan.error = Some(ErrorKind::Detect);
+            planned.push(plan);
+            continue;
+        };
+        let changes = timeout(
+            UPDATE_TIMEOUT,
+            plan_ddns_record(&config.domain, &provider, addr),
+        )
+        .await
+        .unwrap_or_else(|_| Err(ProviderError::Transient("query records timed out".into())));
+        match changes {
+            Ok(changes) => plan.changes = changes,
+            Err(e) => {
+                plan.error = Some(e.kind());
+                plan.message = Some(e.to_string());
+            }
+        }
+        planned.push(plan);
+    }
+    Ok(planned)
+}
+
+/// interfaces of the enabled `nic` methods
+fn watched_interfaces(config: &DnsConfig) -> Vec<String> {
+    [&config.ipv4, &config.ipv6]
+        .into_iter()
+        .flatten()
+        .filter(|cfg| cfg.enabled)
+        .filter_map(|cfg| match &cfg.method {
+            Method::Nic { interface } => Some(interface.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// resolves once one of `interfaces` changed, never if there is none to watch
+async fn changed(events: &mut Receiver<String>, interfaces: &[String]) -> String {
+    if interfaces.is_empty() {
+        return std::future::pending().await;
+    }
+    loop {
+        match events.recv().await {
+            Ok(name) if interfaces.contains(&name) => return name,
+            Ok(_) => continue,
+            // missed some events, one of them may be ours
+            Err(RecvError::Lagged(_)) => return interfaces.join(","),
+            Err(RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
+
+/// the updated families and how long each took
 async fn update(
     domain: &Domain,
     provider: Option<Provider>,
     configs: &mut [(&mut AddrConfig, Family)],
-) {
+    detect: &DetectCache,
+    since: Option<Instant>,
+) -> Vec<(Family, Duration)> {
+    let mut updated = Vec::with_capacity(configs.len());
     let Some(provider) = provider else {
-        return;
+        return updated;
     };
     let timestamp = UtcDateTime::now().unix_timestamp() as _;
     for (cfg, family) in configs {
         if !cfg.enabled {
             continue;
         }
-        let state = match do_update(domain, &provider, (cfg, *family)).await {
+        let started = Instant::now();
+        let state = match do_update(domain, &provider, (cfg, *family), detect, since).await {
             Ok(addr) => {
                 info!("update [{family}] success: {addr}");
                 DnsState::Succeed { addr, timestamp }
@@ -78,14 +327,24 @@ async fn update(
             Err(e) => {
                 let message = format!("{:#}", e);
                 error!("update [{family}] failure: {message}");
-                DnsState::Failed { message, timestamp }
+                let error = match e.downcast_ref::<ProviderError>() {
+                    Some(e) => e.kind(),
+                    None => ErrorKind::Detect,
+                };
+                DnsState::Failed {
+                    message,
+                    timestamp,
+                    error: Some(error),
+                }
             }
         };
         cfg.state = Some(state);
+        updated.push((*family, started.elapsed()));
     }
+    updated
 }
 
-async fn notify(config: &DnsConfig, store: &StoreService) {
+pub async fn notify(config: &DnsConfig, store: &StoreService) {
     if let Some(name) = &config.webhook
         && let Some(webhook) = store.get_webhook(name).await
     {
@@ -100,47 +359,78 @@ async fn do_update(
     domain: &Domain,
     provider: &Provider,
     (config, family): (&AddrConfig, Family),
+    detect: &DetectCache,
+    since: Option<Instant>,
 ) -> anyhow::Result<IpAddr> {
-    let addr = find_addr(config, family)
-        .await?
-        .ok_or_else(|| anyhow::anyhow!("cannot find valid ip address"))?;
-    update_ddns_record(domain, provider, addr).await?;
+    let addr = retry("detect", ATTEMPTS, || async {
+        timeout(DETECT_TIMEOUT, detect.find_addr(config, family, since))
+            .await
+            .context("detect address timed out")?
+    })
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("cannot find valid ip address"))?;
+    retry("update", ATTEMPTS, || async {
+        match timeout(UPDATE_TIMEOUT, update_ddns_record(domain, provider, addr)).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(ProviderError::Transient("update dns record timed out".into()).into()),
+        }
+    })
+    .await?;
     Ok(addr)
 }
 
-async fn find_addr(config: &AddrConfig, family: Family) -> anyhow::Result<Option<IpAddr>> {
-    let addr = match &config.method {
-        Method::Api { endpoint } => {
-            let data = reqwest::Client::new()
-                .get(&*endpoint)
-                .header("User-Agent", "curl/0.0.0")
-                .send()
-                .await?
-                .bytes()
-                .await?;
-            let ip = String::from_utf8(data.to_vec())?;
-            let addr = IpAddr::from_str(&ip)?;
-            vec![addr]
-        }
-        Method::Nic { interface } => local_ip_address::list_afinet_netifas()?
-            .into_iter()
-            .filter_map(|(name, addr)| (&name == &*interface).then_some(addr))
-            .collect::<Vec<IpAddr>>(),
-        Method::Cmd { command } => {
-            let output = Command::new("sh").args(["-c", &*command]).output().await?;
-            let addr = String::from_utf8(output.stdout.to_vec())?;
-            let addr = IpAddr::from_str(&addr.trim())?;
-            vec![addr]
-        }
-    };
-    Ok(filter(addr, family))
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::store::testing::{inline_store, sample_config};
+    use tokio::sync::broadcast;
 
-// #[inline]
-fn filter(addrs: Vec<IpAddr>, family: Family) -> Option<IpAddr> {
-    let check = |addr: &IpAddr| match family {
-        Ipv4 => addr.is_ipv4(),
-        Ipv6 => addr.is_ipv6(),
-    };
-    addrs.into_iter().filter(check).next()
+    #[tokio::test(start_paused = true)]
+    async fn paused_task() {
+        // without the provider the runs don't reach the network
+        let mut home = sample_config("cf");
+        home["ipv4"] = serde_json::json!({ "enabled": true, "method": "cmd", "command": "exit 1" });
+        home["paused"] = true.into();
+        let content = serde_json::json!({ "listen": "0.0.0.0:6789", "ddns": [home] });
+        let store = Arc::new(inline_store("paused", &content.to_string()).await);
+        let config = store.get_dns_config("home").await.unwrap();
+        let (_events, receiver) = broadcast::channel(1);
+        let (commands, commands_receiver) = mpsc::channel(1);
+        let status = Arc::new(TaskStatus::default());
+        let task = tokio::spawn(run_ddns_updating_task(
+            config.clone(),
+            store,
+            Default::default(),
+            receiver,
+            Arc::new(Mutex::new(commands_receiver)),
+            status.clone(),
+            false,
+        ));
+
+        // a paused config skips its ticks and manual runs
+        sleep(Duration::from_secs(300)).await;
+        commands.send(Command::RunNow).await.unwrap();
+        sleep(Duration::from_secs(1)).await;
+        let state = status.get();
+        assert_eq!(state.runs, 0);
+        assert_eq!(state.next_run, None);
+
+        // resuming runs it at once, then on every tick
+        let resumed = DnsConfig {
+            paused: false,
+            ..config
+        };
+        commands
+            .send(Command::Reload(resumed.into()))
+            .await
+            .unwrap();
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(status.get().runs, 1);
+        assert!(status.get().next_run.is_some());
+        sleep(Duration::from_secs(300)).await;
+        assert!(status.get().runs > 1);
+
+        commands.send(Command::Stop).await.unwrap();
+        task.await.unwrap();
+    }
 }
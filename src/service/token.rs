@@ -1,8 +1,14 @@
+use anyhow::Context;
 use papaya::HashMap;
 use rand::{RngCore, rng};
-use std::{ops::Add, sync::Arc};
-use time::{Duration, OffsetDateTime};
-use tokio::spawn;
+use serde::{Deserialize, Serialize};
+use std::{ops::Add, path::Path, sync::Arc};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tokio::{
+    fs::{OpenOptions, read_to_string, remove_file},
+    io::AsyncWriteExt,
+    spawn,
+};
 use tracing::{info, trace};
 
 pub struct TokenService {
@@ -18,10 +24,18 @@ impl TokenService {
     pub async fn generate_token(&self) -> [u8; 32] {
         const SESSION_TTL: Duration = Duration::days(1);
 
+        self.insert(OffsetDateTime::now_utc().add(SESSION_TTL))
+    }
+
+    /// a token valid until the server stops, for the cli on the same host
+    pub fn generate_local_token(&self) -> [u8; 32] {
+        self.insert(PrimitiveDateTime::MAX.assume_utc())
+    }
+
+    fn insert(&self, expires: OffsetDateTime) -> [u8; 32] {
         let mut bytes = [0u8; 32];
         rng().fill_bytes(&mut bytes);
-        let now = OffsetDateTime::now_utc().add(SESSION_TTL);
-        self.tokens.pin_owned().insert(bytes, now);
+        self.tokens.pin_owned().insert(bytes, expires);
         trace!("generated token generated bytes: {:?}", bytes);
         bytes
     }
@@ -56,3 +70,74 @@ impl TokenService {
         });
     }
 }
+
+/// written next to the state file while the server runs, the cli uses it to reach the api
+#[derive(Serialize, Deserialize)]
+pub struct LocalToken {
+    /// e.g. `http://127.0.0.1:6789`
+    pub url: String,
+    /// base64, as sent in the authorization header
+    pub token: String,
+}
+
+impl LocalToken {
+    /// only readable by the owner, like the key file
+    pub async fn write(&self, path: &Path) -> anyhow::Result<()> {
+        // the mode is only set on creation
+        let _ = remove_file(path).await;
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options
+            .open(path)
+            .await
+            .with_context(|| format!("create token file {}", path.to_string_lossy()))?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())
+            .await?;
+        // the write runs in the background until flushed, the cli could read it empty
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// none if no server wrote it
+    pub async fn read(path: &Path) -> Option<Self> {
+        let content = read_to_string(path).await.ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_token_file() {
+        let path = std::env::temp_dir().join(format!("ddns-rs-{}.token", std::process::id()));
+        assert!(LocalToken::read(&path).await.is_none());
+        let token = |token: &str| LocalToken {
+            url: "http://127.0.0.1:6789".to_string(),
+            token: token.to_string(),
+        };
+        token("first").write(&path).await.unwrap();
+        // left by a server that was killed
+        token("second").write(&path).await.unwrap();
+        let read = LocalToken::read(&path).await.unwrap();
+        assert_eq!(read.url, "http://127.0.0.1:6789");
+        assert_eq!(read.token, "second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = tokio::fs::metadata(&path)
+                .await
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        tokio::fs::write(&path, "{\"url\": ").await.unwrap();
+        assert!(LocalToken::read(&path).await.is_none());
+        remove_file(&path).await.unwrap();
+    }
+}
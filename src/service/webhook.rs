@@ -1,9 +1,12 @@
-use crate::model::{DnsConfig, DnsState, Webhook};
+use crate::{
+    model::{AddrConfig, DnsConfig, DnsState, Domain, ErrorKind, Method, Webhook},
+    service::retry::http_client,
+};
 use reqwest::{
-    Body, Client,
+    Body,
     header::{HeaderMap, HeaderName, HeaderValue},
 };
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, net::IpAddr};
 use tracing::info;
 
 pub async fn notify(config: &DnsConfig, webhook: &Webhook) -> anyhow::Result<String> {
@@ -21,7 +24,8 @@ pub async fn notify(config: &DnsConfig, webhook: &Webhook) -> anyhow::Result<Str
     let variables = construct_variables(config);
     let uri = replace_variables(str::from_utf8(uri)?, &variables);
     let body = replace_variables(str::from_utf8(body)?, &variables);
-    let builder = Client::new()
+    let builder = http_client()
+        .build()?
         .request(method, uri.to_string())
         .headers(req_headers)
         .body(Body::from(body.to_string()));
@@ -30,6 +34,60 @@ pub async fn notify(config: &DnsConfig, webhook: &Webhook) -> anyhow::Result<Str
     Ok(response)
 }
 
+/// whether `template` is a request [`notify`] can send
+pub fn check_template(template: &str) -> anyhow::Result<()> {
+    let request = parser::parse(template)
+        .map_err(|e| anyhow::anyhow!(format!("failed to parse request: \n{:#}", e)))?;
+    for (n, v) in request.headers {
+        HeaderName::from_bytes(n)?;
+        HeaderValue::from_bytes(v)?;
+    }
+    Ok(())
+}
+
+/// a config with a succeeded ipv4 and a failed ipv6 update, to try a webhook
+pub fn sample_config(webhook: &str) -> DnsConfig {
+    DnsConfig {
+        name: format!("test-webhook-{webhook}"),
+        domain: Domain {
+            domain: "example.com".to_string(),
+            subdomain: "@".to_string(),
+        },
+        interval: 0,
+        ipv4: AddrConfig {
+            enabled: true,
+            method: Method::Nic {
+                interface: "test".to_string(),
+            },
+            suffix: None,
+            state: DnsState::Succeed {
+                addr: IpAddr::V4([127, 0, 0, 1].into()),
+                timestamp: 0,
+            }
+            .into(),
+        }
+        .into(),
+        ipv6: Some(AddrConfig {
+            enabled: true,
+            method: Method::Api {
+                endpoint: "https://baidu.com".to_string(),
+            },
+            suffix: None,
+            state: DnsState::Failed {
+                message: "test failed".to_string(),
+                timestamp: 0,
+                error: Some(ErrorKind::Auth),
+            }
+            .into(),
+        }),
+        provider: "".to_string(),
+        webhook: Some(webhook.to_string()),
+        schedule: None,
+        paused: false,
+        restart: None,
+    }
+}
+
 fn replace_variables<'a>(template: &'a str, variables: &HashMap<&str, String>) -> Cow<'a, str> {
     let mut template = Cow::Borrowed(template);
     for (key, value) in variables {
@@ -55,13 +113,15 @@ fn construct_variables(config: &DnsConfig) -> HashMap<&'static str, String> {
                 variables.insert("ipv4.state", "succeed".to_string());
                 variables.insert("ipv4.addr", addr.to_string());
             }
-            DnsState::Failed { message, .. } => {
+            DnsState::Failed { message, error, .. } => {
                 variables.insert("ipv4.state", "failed".to_owned());
                 variables.insert("ipv4.message", message.to_owned());
+                if let Some(error) = error {
+                    variables.insert("ipv4.error", error.to_string());
+                }
             }
         }
     }
-    let mut variables = HashMap::new();
     if let Some(ipv6) = &config.ipv6
         && let Some(state) = &ipv6.state
     {
@@ -70,9 +130,12 @@ fn construct_variables(config: &DnsConfig) -> HashMap<&'static str, String> {
                 variables.insert("ipv6.state", "succeed".to_string());
                 variables.insert("ipv6.addr", addr.to_string());
             }
-            DnsState::Failed { message, .. } => {
+            DnsState::Failed { message, error, .. } => {
                 variables.insert("ipv6.state", "failed".to_owned());
                 variables.insert("ipv6.message", message.to_owned());
+                if let Some(error) = error {
+                    variables.insert("ipv6.error", error.to_string());
+                }
             }
         }
     }
@@ -277,4 +340,27 @@ mod tests {
             "GET https://google.com/?ipv4Addr=#{ipv4.addr&ipv6Addr=::1"
         )
     }
+
+    #[test]
+    fn variables_of_both_families() {
+        let mut config: DnsConfig = serde_json::from_value(serde_json::json!({
+            "name": "home",
+            "domain": "example.com",
+            "subdomain": "www",
+            "interval": 300,
+            "ipv4": { "enabled": true, "method": "api", "endpoint": "https://4.ipw.cn" },
+            "ipv6": { "enabled": true, "method": "api", "endpoint": "https://6.ipw.cn" },
+            "provider": "cf",
+        }))
+        .unwrap();
+        let state = |addr: &str| DnsState::Succeed {
+            timestamp: 0,
+            addr: addr.parse().unwrap(),
+        };
+        config.ipv4.as_mut().unwrap().state = Some(state("1.2.3.4"));
+        config.ipv6.as_mut().unwrap().state = Some(state("::1"));
+        let variables = construct_variables(&config);
+        assert_eq!(variables["ipv4.addr"], "1.2.3.4");
+        assert_eq!(variables["ipv6.addr"], "::1");
+    }
 }